@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Sender, Receiver};
+use std::thread;
+use rodio::source::Source;
+
+use crate::audio::{self, MusicFormat};
+use crate::settings;
+
+type Job = (PathBuf, MusicFormat);
+type Cache = Arc<Mutex<HashMap<PathBuf, Waveform>>>;
+
+/// Peak and RMS envelope of a whole track, one value per column of the overview.
+#[derive(Debug, Clone)]
+pub struct Waveform {
+    pub peak: Vec<f32>,
+    pub rms: Vec<f32>,
+    pub duration: f64,
+}
+
+pub struct Analyzer {
+    tx: Sender<Job>,
+    cache: Cache,
+    state: Arc<Mutex<crate::State>>,
+}
+
+impl Analyzer {
+    pub fn spawn(state: Arc<Mutex<crate::State>>) -> Analyzer {
+        let (tx, rx): (Sender<Job>, Receiver<Job>) = mpsc::channel();
+        let cache: Cache = Arc::new(Mutex::new(HashMap::new()));
+
+        let cache_worker = Arc::clone(&cache);
+        let state_worker = Arc::clone(&state);
+        thread::spawn(move || worker(rx, cache_worker, state_worker));
+
+        Analyzer {
+            tx,
+            cache,
+            state,
+        }
+    }
+
+    /// Publishes the cached waveform of `path` right away, or queues it for the worker.
+    pub fn request(&self, path: &Path, ext: MusicFormat) {
+        let cache = self.cache.lock().unwrap();
+        match cache.get(path) {
+            Some(waveform) => publish(&self.state, path, waveform),
+            None => self.tx.send((path.to_path_buf(), ext)).unwrap(),
+        }
+    }
+}
+
+fn worker(rx: Receiver<Job>, cache: Cache, state: Arc<Mutex<crate::State>>) {
+    while let Ok(mut job) = rx.recv() {
+        // Only the latest track matters when skipping through several in a row
+        while let Ok(next) = rx.try_recv() {
+            job = next;
+        }
+        let (path, ext) = job;
+        if cache.lock().unwrap().contains_key(&path) {
+            continue;
+        }
+        if let Some(waveform) = analyze(&path, ext) {
+            publish(&state, &path, &waveform);
+            cache.lock().unwrap().insert(path, waveform);
+        }
+    }
+}
+
+fn publish(state: &Arc<Mutex<crate::State>>, path: &Path, waveform: &Waveform) {
+    let mut s_analysis = state.lock().unwrap();
+    if s_analysis.file_path == path {
+        s_analysis.duration = waveform.duration;
+        s_analysis.waveform = Some(waveform.clone());
+    }
+}
+
+/// Decodes the whole file once, collecting peak and sum of squares per block of
+/// frames, then folds the blocks into `settings::WAVEFORM_COLUMNS` columns.
+pub fn analyze(path: &Path, ext: MusicFormat) -> Option<Waveform> {
+    let file = File::open(path).ok()?;
+    let decoder = audio::open_decoder(file, ext).ok()?;
+    let channels = decoder.channels().max(1) as usize;
+    let rate = decoder.sample_rate().max(1) as f64;
+    let block_len = settings::WAVEFORM_BLOCK * channels;
+
+    let mut blocks: Vec<(f32, f32, usize)> = Vec::new();
+    let (mut peak, mut sum, mut len) = (0.0f32, 0.0f32, 0usize);
+    let mut total: u64 = 0;
+
+    for sample in decoder {
+        let v = (sample as f32 / 32768.0).abs();
+        peak = peak.max(v);
+        sum += v * v;
+        len += 1;
+        total += 1;
+        if len == block_len {
+            blocks.push((peak, sum, len));
+            (peak, sum, len) = (0.0, 0.0, 0);
+        }
+    }
+    if len > 0 {
+        blocks.push((peak, sum, len));
+    }
+    if blocks.is_empty() {
+        return None;
+    }
+
+    let mut waveform = Waveform {
+        peak: vec![0.0; settings::WAVEFORM_COLUMNS],
+        rms: vec![0.0; settings::WAVEFORM_COLUMNS],
+        duration: total as f64 / (rate * channels as f64),
+    };
+
+    for c in 0..settings::WAVEFORM_COLUMNS {
+        let first = c * blocks.len() / settings::WAVEFORM_COLUMNS;
+        let last = ((c + 1) * blocks.len() / settings::WAVEFORM_COLUMNS).max(first + 1);
+        let (mut peak, mut sum, mut len) = (0.0f32, 0.0f32, 0usize);
+        for block in &blocks[first..last.min(blocks.len())] {
+            peak = peak.max(block.0);
+            sum += block.1;
+            len += block.2;
+        }
+        waveform.peak[c] = peak;
+        waveform.rms[c] = (sum / len.max(1) as f32).sqrt();
+    }
+
+    Some(waveform)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_wav::TestWav;
+
+    #[test]
+    fn folds_a_track_into_columns() {
+        // three seconds of mono at 8 kHz, silent in the first half and at half scale after
+        let samples: Vec<i16> = (0..24000).map(|n| if n < 12000 { 0 } else { 16384 }).collect();
+        let wav = TestWav::new(8000, 1, &samples);
+        let waveform = analyze(&wav.path, MusicFormat::WAV).unwrap();
+        assert_eq!(waveform.peak.len(), settings::WAVEFORM_COLUMNS);
+        assert_eq!(waveform.rms.len(), settings::WAVEFORM_COLUMNS);
+        assert!((waveform.duration - 3.0).abs() < 1e-9, "{}", waveform.duration);
+        assert_eq!(waveform.peak[0], 0.0);
+        assert_eq!(waveform.peak[settings::WAVEFORM_COLUMNS - 1], 0.5);
+        assert!((waveform.rms[settings::WAVEFORM_COLUMNS - 1] - 0.5).abs() < 1e-6);
+    }
+}
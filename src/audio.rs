@@ -1,26 +1,104 @@
 use std::sync::{Arc, Mutex};
 use std::fs;
-use std::io::{Read, Seek};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::io::{BufReader, Read, Seek};
 use std::marker::Sync;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 use rodio::decoder::{Decoder, DecoderError};
 use rodio::source::Source;
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
 use std::sync::mpsc::{self, Sender, Receiver};
 
+use crate::analysis;
+use crate::beat;
+use crate::config::PlayMode;
+use crate::history;
+use crate::scrobbler;
+use crate::seekable::SeekableDecoder;
+use crate::session;
 use crate::settings;
+use crate::tags;
 
-pub fn playback(state_player: Arc<Mutex<crate::State>>) {
+/// Interleaved samples, position in seconds, channels and sample rate.
+type Tap = ([i16; settings::SAMPLES], f64, u16, u32);
+
+/// Plays the configured music folders, or the current directory, over and over,
+/// from `resume` if given.
+pub fn playback(state_player: Arc<Mutex<crate::State>>, mut resume: Option<session::Resume>) {
     let current_dir = std::env::current_dir().expect("Can't find current directory");
-    let (_stream, handle) = rodio::OutputStream::try_default().expect("Can't open output stream (Rodio)");
-    let (tx, rx): (Sender<[i16; settings::SAMPLES]>, Receiver<[i16; settings::SAMPLES]>) = mpsc::channel();
+    let output = state_player.lock().unwrap().config.playback.output.clone();
+    let (_stream, handle) = open_output(&state_player, &output);
+    let (tx, rx): (Sender<Tap>, Receiver<Tap>) = mpsc::channel();
     let sink = rodio::Sink::try_new(&handle).expect("Can't create Rodio Sink");
+    let analyzer = analysis::Analyzer::spawn(Arc::clone(&state_player));
+    let mut detector = beat::BeatDetector::init();
+    let mut empty_reported = false;
+    let mut missing_reported: Vec<PathBuf> = Vec::new();
 
     loop {
-        let mut counter = 0;
-        let entries = fs::read_dir(current_dir.clone()).expect("ReadDir error");
-        for entry in entries {
-            let path = entry.expect("Reading entry path error").path();
-            let pstr = format!("{}", current_dir.display());
+        // the folders are read again on every pass, so a changed configuration applies from the next one
+        let mut dirs = state_player.lock().unwrap().config.playback.music_dirs.clone();
+        let recursive = !dirs.is_empty();
+        if dirs.is_empty() {
+            dirs.push(current_dir.clone());
+        }
+        // said once per folder that goes missing, such as an unplugged drive
+        let missing: Vec<PathBuf> = dirs.iter().filter(|dir| !dir.is_dir()).cloned().collect();
+        for dir in missing.iter().filter(|dir| !missing_reported.contains(dir)) {
+            let mut s_player = state_player.lock().unwrap();
+            s_player.notifications.warning(format!("{} is not a folder, skipped", dir.display()));
+            drop(s_player);
+        }
+        missing_reported = missing;
+        let mut entries: Vec<PathBuf> = Vec::new();
+        for dir in dirs.iter() {
+            music_files(dir, recursive, &mut entries);
+        }
+        let mut s_player = state_player.lock().unwrap();
+        s_player.playlist = entries.clone();
+        drop(s_player);
+        tags::scan(Arc::clone(&state_player), entries.clone());
+        if entries.is_empty() {
+            if !empty_reported {
+                let mut s_player = state_player.lock().unwrap();
+                let names: Vec<String> = dirs.iter().map(|d| d.display().to_string()).collect();
+                s_player.notifications.warning(format!("no music files in {}", names.join(", ")));
+                drop(s_player);
+                empty_reported = true;
+            }
+            std::thread::sleep(Duration::from_secs(1));
+            continue;
+        }
+        empty_reported = false;
+        // files that could not be opened or decoded in this pass
+        let mut failed = 0;
+        // indices of the tracks played in this pass, for going back
+        let mut history: Vec<usize> = Vec::new();
+        let mut index = 0;
+        // the playlist track that was playing when queued tracks took over, to go on after it
+        let mut after_queue: Option<usize> = None;
+        // queued files from elsewhere are appended after these
+        let listed = entries.len();
+        // where the first track starts, in seconds
+        let mut start = 0.0;
+        if let Some(resume) = resume.take() {
+            match entries.iter().position(|entry| *entry == resume.track) {
+                Some(found) => {
+                    index = found;
+                    start = resume.position;
+                }
+                None if resume.index < entries.len() => index = resume.index,
+                None => {}
+            }
+        }
+        while index < entries.len() {
+            let path = entries[index].clone();
+            let mut previous = false;
+            let mut jump = None;
+            // played to its end rather than left for another track
+            let mut finished = false;
+            let pstr = format!("{}", path.parent().unwrap_or(&current_dir).display());
             let mut s_player = state_player.lock().unwrap();
             let dir_name = dir_name(&pstr);
             s_player.dir_name = dir_name;
@@ -28,41 +106,96 @@ pub fn playback(state_player: Arc<Mutex<crate::State>>) {
             let pstr = format!("{}", path.display());
             let ext = track_format(&pstr);
             let ext_text = ext.display();
-            let file_open = std::fs::File::open(path);
+            let file_open = File::open(&path);
+            // only the resumed track starts mid-way
+            let start = std::mem::take(&mut start);
+            let sampling_time = state_player.lock().unwrap().config.playback.sampling_time;
             match file_open {
                 Ok(file) => {
-                    let res = SpyDecoder::new(file, ext);
+                    let res = spy_source(file, ext, start, sampling_time, tx.clone());
 
                     match res {
-                        Ok(buff) => {
-                            counter += 1;
-                            let tx2 = tx.clone();
-                            let buffc = buff.periodic_access(
-                                std::time::Duration::from_secs_f64(settings::SAMPLING_TIME), 
-                                move |s| {
-                                    tx2.send(s.stats).unwrap()
-                                });
+                        Ok((buffc, start)) => {
+                            history.push(index);
+                            let mut s_player = state_player.lock().unwrap();
+                            s_player.file_path = path.clone();
+                            s_player.position = start;
+                            s_player.duration = buffc.total_duration().map_or(0.0, |d| d.as_secs_f64());
+                            s_player.waveform = None;
+                            s_player.seek = None;
+                            s_player.tempo = 0.0;
+                            drop(s_player);
+                            detector.reset();
+                            analyzer.request(&path, ext);
                             sink.append(buffc);
+                            let started = SystemTime::now();
+                            // seconds actually played, for the history
+                            let mut listened = 0.0;
+                            let mut tick = Instant::now();
                             loop {
+                                let tick_time = tick.elapsed().as_secs_f64();
+                                tick = Instant::now();
                                 let mut s_player = state_player.lock().unwrap();
-                                s_player.file_num = counter;
+                                s_player.file_num = index + 1;
+                                s_player.track_index = index;
                                 let file_name = track_name(&pstr);
                                 s_player.file_name = file_name;
                                 s_player.file_ext = ext_text.clone();
 
+                                if let Some(target) = s_player.seek.take() {
+                                    let target = target.clamp(0.0, s_player.duration);
+                                    // the window locks the state every frame, so the decoder is
+                                    // opened and moved to the target without holding it
+                                    drop(s_player);
+                                    let seeked = File::open(&path)
+                                        .ok()
+                                        .and_then(|file| spy_source(file, ext, target, sampling_time, tx.clone()).ok());
+                                    s_player = state_player.lock().unwrap();
+                                    if let Some((buffc, reached)) = seeked {
+                                        sink.clear();
+                                        while rx.try_recv().is_ok() {}
+                                        sink.append(buffc);
+                                        s_player.position = reached;
+                                        detector.reset();
+                                    } else {
+                                        s_player.notifications.error("seek: could not reopen the track".to_string());
+                                    }
+                                }
+
+                                if let Some(target) = s_player.play_index.take() {
+                                    s_player.sample_stats = [0; settings::SAMPLES];
+                                    drop(s_player);
+                                    sink.clear();
+                                    sink.stop();
+                                    jump = Some(target);
+                                    break;
+                                }
+
                                 if sink.empty() {
                                     s_player.sample_stats = [0; settings::SAMPLES];
                                     drop(s_player);
                                     sink.clear();
                                     sink.stop();
+                                    finished = true;
                                     break;
                                 }
                 
+                                sink.set_volume(s_player.volume);
+
                                 if s_player.play {
                                     sink.play();
+                                    listened += tick_time;
                                     let send_sample = rx.try_recv();
                                     match send_sample {
-                                        Ok(stats) => s_player.sample_stats = stats,
+                                        Ok((stats, position, channels, rate)) => {
+                                            s_player.sample_stats = stats;
+                                            s_player.position = position;
+                                            if detector.process(&stats, channels, rate, position).is_some() {
+                                                s_player.beats += 1;
+                                                s_player.pulse = 1.0;
+                                            }
+                                            s_player.tempo = detector.tempo;
+                                        },
                                         Err(_) => {}
                                     }
                                     if s_player.skip {
@@ -73,24 +206,240 @@ pub fn playback(state_player: Arc<Mutex<crate::State>>) {
                                         sink.stop();
                                         break;
                                     }
+                                    if s_player.previous {
+                                        s_player.previous = false;
+                                        if s_player.position > settings::RESTART_TIME {
+                                            s_player.seek = Some(0.0);
+                                        } else {
+                                            s_player.sample_stats = [0; settings::SAMPLES];
+                                            drop(s_player);
+                                            sink.clear();
+                                            sink.stop();
+                                            previous = true;
+                                            break;
+                                        }
+                                    }
                                 } else {
                                     sink.pause();
                                 }
+                                let frame_time = s_player.config.display.frame_time();
+                                s_player.pulse *= (-frame_time * settings::PULSE_DECAY).exp() as f32;
                                 drop(s_player);
                 
-                                std::thread::sleep(std::time::Duration::from_secs_f64(settings::FT_DESIRED));
+                                std::thread::sleep(std::time::Duration::from_secs_f64(frame_time));
                             }
+                            track_ended(&state_player, &path, started, listened, finished);
                         },
-                        Err(_) => {}
+                        Err(_) => failed += 1,
                     }
                 }
-                Err(_) => {}
+                Err(_) => failed += 1,
+            }
+            let repeat_one = finished && state_player.lock().unwrap().play_mode == PlayMode::RepeatOne;
+            if let Some(target) = jump {
+                index = target.min(entries.len() - 1);
+                after_queue = None;
+            } else if previous {
+                // drop the current track, then step back to the one before it
+                history.pop();
+                index = history.pop().unwrap_or(index);
+                after_queue = None;
+            } else if let Some(queued) = next_queued(&state_player, &mut entries) {
+                after_queue.get_or_insert(index);
+                index = queued;
+            } else if let Some(interrupted) = after_queue.take() {
+                index = if repeat_one { interrupted } else { step(interrupted, listed, entries.len()) };
+            } else if repeat_one {
+                // the same track again
+            } else {
+                index = step(index, listed, entries.len());
+            }
+        }
+        let mut s_player = state_player.lock().unwrap();
+        if s_player.play_mode == PlayMode::Once {
+            s_player.play = false;
+        }
+        drop(s_player);
+        if failed > 0 {
+            let mut s_player = state_player.lock().unwrap();
+            let files = if failed == 1 { "file" } else { "files" };
+            s_player.notifications.warning(format!("{} {} could not be decoded", failed, files));
+            drop(s_player);
+            if failed == entries.len() {
+                std::thread::sleep(Duration::from_secs(1));
             }
         }
     }
     
 }
 
+/// Opens a decoder starting `start` seconds into the track, with the sample tap
+/// sending its statistics and playback position to `tx` every `sampling_time` seconds.
+/// Also returns where it starts, as seeking lands where the stream allows. mp3, wav
+/// and flac seek in the stream, other formats decode their way to `start`.
+fn spy_source(file: File, ext: MusicFormat, start: f64, sampling_time: f64, tx: Sender<Tap>) -> Result<(impl Source<Item = i16> + Send, f64), DecoderError> {
+    let buff = match ext {
+        MusicFormat::MP3 | MusicFormat::WAV | MusicFormat::FLAC => {
+            let mut inner = SeekableDecoder::open(file, &ext.display()).ok_or(DecoderError::UnrecognizedFormat)?;
+            let start = inner.seek(start);
+            SpyDecoder::new(Box::new(inner), start)
+        }
+        _ => {
+            let mut buff = SpyDecoder::new(Box::new(open_decoder(file, ext)?), 0.0);
+            buff.skip_to(start);
+            buff
+        }
+    };
+    let start = buff.elapsed();
+    Ok((buff.periodic_access(
+        std::time::Duration::from_secs_f64(sampling_time), 
+        move |s| {
+            tx.send((s.stats, s.elapsed(), s.channels(), s.sample_rate())).unwrap()
+        }), start))
+}
+
+/// The output device called `name`, or the system default when `name` is empty
+/// or no device has it; the latter says so in a notification.
+fn open_output(state_player: &Arc<Mutex<crate::State>>, name: &str) -> (rodio::OutputStream, rodio::OutputStreamHandle) {
+    if !name.is_empty() {
+        let devices: Vec<rodio::Device> = rodio::cpal::default_host()
+            .output_devices()
+            .map(|devices| devices.collect())
+            .unwrap_or_default();
+        if let Some(device) = devices.iter().find(|d| d.name().is_ok_and(|n| n == name)) {
+            match rodio::OutputStream::try_from_device(device) {
+                Ok(output) => return output,
+                Err(error) => {
+                    let mut s_player = state_player.lock().unwrap();
+                    s_player.notifications.warning(format!("output: {}: {}, using the default", name, error));
+                    drop(s_player);
+                }
+            }
+        } else {
+            let names: Vec<String> = devices.iter().filter_map(|d| d.name().ok()).collect();
+            let known = if names.is_empty() { String::new() } else { format!(" (there are {})", names.join(", ")) };
+            let mut s_player = state_player.lock().unwrap();
+            s_player.notifications.warning(format!("output: no device {}{}, using the default", name, known));
+            drop(s_player);
+        }
+    }
+    rodio::OutputStream::try_default().expect("Can't open output stream (Rodio)")
+}
+
+/// Adds the music files in `dir` to `entries`, sorted, those of its subfolders too when `recursive`.
+fn music_files(dir: &Path, recursive: bool, entries: &mut Vec<PathBuf>) {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(read) => read.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+        Err(_) => return,
+    };
+    paths.sort();
+    for path in paths {
+        if path.is_file() && is_music(&path) {
+            entries.push(path);
+        } else if recursive && path.is_dir() {
+            music_files(&path, recursive, entries);
+        }
+    }
+}
+
+/// Extensions that sit next to music in album folders but are never audio.
+const NOT_MUSIC: [&str; 22] = [
+    "jpg", "jpeg", "png", "gif", "bmp", "webp", "tif", "tiff",
+    "txt", "nfo", "log", "cue", "md", "pdf", "htm", "html",
+    "m3u", "m3u8", "pls", "xspf", "db", "ini",
+];
+
+/// A file with one of the known extensions, or another one that starts like a stream
+/// the decoder knows. Only files that neither list nor header tell apart get the
+/// full decoder probe.
+fn is_music(path: &Path) -> bool {
+    if !matches!(track_format(&format!("{}", path.display())), MusicFormat::GEN) {
+        return true;
+    }
+    let ext = path.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+    if NOT_MUSIC.contains(&ext.as_str()) {
+        return false;
+    }
+    let mut head = [0u8; 12];
+    let read = match File::open(path).and_then(|mut file| file.read(&mut head)) {
+        Ok(read) => read,
+        Err(_) => return false,
+    };
+    match sniff(&head[..read]) {
+        Some(_) => true,
+        None if read < head.len() => false,
+        None => File::open(path).is_ok_and(|file| Decoder::new(BufReader::new(file)).is_ok()),
+    }
+}
+
+/// The format whose magic bytes `head` starts with.
+fn sniff(head: &[u8]) -> Option<MusicFormat> {
+    match head {
+        [b'I', b'D', b'3', ..] => Some(MusicFormat::MP3),
+        // an MPEG layer III frame header, with a valid bitrate and sample rate
+        [0xFF, second, third, ..] if second & 0xE6 == 0xE2 && third >> 4 != 0xF && (third >> 2) & 3 != 3 => Some(MusicFormat::MP3),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some(MusicFormat::WAV),
+        [b'f', b'L', b'a', b'C', ..] => Some(MusicFormat::FLAC),
+        [b'O', b'g', b'g', b'S', ..] => Some(MusicFormat::OGG),
+        _ => None,
+    }
+}
+
+/// Emits the play of the track that just ended to the history and the scrobble log.
+fn track_ended(state_player: &Arc<Mutex<crate::State>>, path: &Path, started: SystemTime, listened: f64, finished: bool) {
+    let s_player = state_player.lock().unwrap();
+    let duration = s_player.duration;
+    let tags = s_player.tags.get(path).cloned();
+    drop(s_player);
+    let tags = tags.unwrap_or_else(|| tags::Tags::read(path));
+    let play = history::Play::new(path, tags, started, duration, listened, finished);
+    let mut errors = Vec::new();
+    if let Err(error) = history::record(&history::db_path(), &play) {
+        errors.push(format!("history: {}", error));
+    }
+    if let Err(error) = scrobbler::record(&scrobbler::log_path(), &play) {
+        errors.push(format!("scrobble: {}", error));
+    }
+    if !errors.is_empty() {
+        let mut s_player = state_player.lock().unwrap();
+        for error in errors {
+            s_player.notifications.warning(error);
+        }
+        drop(s_player);
+    }
+}
+
+/// The track after `index`. The files the queue appended after the `listed` ones of
+/// the folder were played already, so the end of the folder ends the pass.
+fn step(index: usize, listed: usize, len: usize) -> usize {
+    if index + 1 == listed {
+        len
+    } else {
+        index + 1
+    }
+}
+
+/// Takes the first track off `State::queue` and returns its index in `entries`,
+/// appending it to them and to the playlist when it is from elsewhere.
+fn next_queued(state_player: &Arc<Mutex<crate::State>>, entries: &mut Vec<PathBuf>) -> Option<usize> {
+    let mut s_player = state_player.lock().unwrap();
+    if s_player.queue.is_empty() {
+        return None;
+    }
+    let path = s_player.queue.remove(0);
+    let index = match entries.iter().position(|entry| *entry == path) {
+        Some(index) => index,
+        None => {
+            entries.push(path.clone());
+            s_player.playlist.push(path.clone());
+            drop(s_player);
+            tags::scan(Arc::clone(state_player), vec![path]);
+            entries.len() - 1
+        }
+    };
+    Some(index)
+}
+
 fn track_name(text: &String) -> String {
     let text1: Vec<&str> = text.split(|c| c == '/' || c == '\\').collect();
     let letters: Vec<char> = text1[text1.len()-1].chars().collect();
@@ -110,32 +459,23 @@ fn track_name(text: &String) -> String {
 }
 
 fn dir_name(text: &String) -> String {
-    let text1: Vec<&str> = text.split(|c| c == '/' || c == '\\').collect();
-    let letters: Vec<char> = text1[text1.len()-1].chars().collect();
-    let mut new: String = String::new();
-    for i in 0..(letters.len()).min(40) {
-        new.push(letters[i]);
-    }
-    new
+    text.trim_end_matches(|c| c == '/' || c == '\\').to_string()
 }
 
 fn track_format(text: &String) -> MusicFormat {
     let split: Vec<&str> = text.split(|c| c == '.').collect();
-    let ext = split[split.len()-1];
-    let answ: MusicFormat = match ext {
+    let ext = split[split.len()-1].to_lowercase();
+    let answ: MusicFormat = match ext.as_str() {
         "mp3" => MusicFormat::MP3,
         "wav" => MusicFormat::WAV,
         "ogg" => MusicFormat::OGG,
         "flac" => MusicFormat::FLAC,
-        "MP3" => MusicFormat::MP3,
-        "WAV" => MusicFormat::WAV,
-        "OGG" => MusicFormat::OGG,
-        "FLAC" => MusicFormat::FLAC,
         _ => MusicFormat::GEN,
     };
     answ
 }
 
+#[derive(Debug, Clone, Copy)]
 pub enum MusicFormat {
     MP3,
     WAV,
@@ -156,51 +496,78 @@ impl MusicFormat {
     }
 }
 
+pub fn open_decoder<R>(file: R, ext: MusicFormat) -> Result<Decoder<R>, DecoderError>
+    where
+        R: Read + Seek + Send + Sync + 'static,
+{
+    match ext {
+        MusicFormat::MP3 => Decoder::new_mp3(file),
+        MusicFormat::WAV => Decoder::new_wav(file),
+        MusicFormat::OGG => Decoder::new_vorbis(file),
+        MusicFormat::FLAC => Decoder::new_flac(file),
+        _ => Decoder::new(file),
+    }
+}
+
 //=============================
 // The following sampling solution is proposed by
 // https://github.com/jmbarbier at https://github.com/RustAudio/rodio/issues/475
 // I have made some changes to use with my own code
 //=============================
 
-pub struct SpyDecoder<R> where R: Read + Seek
+pub struct SpyDecoder
 {
-    inner: Decoder<R>,
+    inner: Box<dyn Source<Item = i16> + Send>,
     stats: [i16; settings::SAMPLES],
     stats_index: usize,
     stats_wait: usize,
     stats_wait_index: usize,
     stats_collect: bool,
+    played: u64,
 }
 
-impl<R> SpyDecoder<R>
-    where
-        R: Read + Seek + Send + Sync + 'static,
-{
-    pub fn new(file: R, ext: MusicFormat) -> Result<SpyDecoder<R>, DecoderError> {
-        let inner = match ext {
-            MusicFormat::MP3 => Decoder::new_mp3(file),
-            MusicFormat::WAV => Decoder::new_wav(file),
-            MusicFormat::OGG => Decoder::new_vorbis(file),
-            MusicFormat::FLAC => Decoder::new_flac(file),
-            _ => Decoder::new(file),
-        }?;
-        Ok(Self {
+impl SpyDecoder {
+    /// Taps `inner`, which starts `start` seconds into the track.
+    pub fn new(inner: Box<dyn Source<Item = i16> + Send>, start: f64) -> SpyDecoder {
+        let played = (start * inner.sample_rate() as f64) as u64 * inner.channels() as u64;
+        Self {
             inner,
             stats: [0; settings::SAMPLES],
             stats_index: 0,
             stats_wait: 44000 / 10,
             stats_wait_index: 0,
             stats_collect: false,
-        })
+            played,
+        }
+    }
+
+
+    /// Playback position in seconds, counted from the samples handed to the sink.
+    pub fn elapsed(&self) -> f64 {
+        let rate = self.inner.sample_rate().max(1) as f64;
+        let channels = self.inner.channels().max(1) as f64;
+        self.played as f64 / (rate * channels)
+    }
+
+    /// Decodes and drops samples until `seconds` into the track.
+    pub fn skip_to(&mut self, seconds: f64) {
+        let rate = self.inner.sample_rate() as f64;
+        let channels = self.inner.channels() as u64;
+        let target = (seconds * rate) as u64 * channels;
+        while self.played < target && self.inner.next().is_some() {
+            self.played += 1;
+        }
     }
 }
 
-impl<R> Iterator for SpyDecoder<R>
-    where R: Read + Seek {
+impl Iterator for SpyDecoder {
     type Item = i16;
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
         let sample = self.inner.next();
+        if sample.is_some() {
+            self.played += 1;
+        }
         if self.stats_collect {
             if self.stats_index < settings::SAMPLES {
                 if sample.is_some() {
@@ -228,8 +595,7 @@ impl<R> Iterator for SpyDecoder<R>
     }
 }
 
-impl<R> Source for SpyDecoder<R>
-    where R: Read + Seek {
+impl Source for SpyDecoder {
     fn current_frame_len(&self) -> Option<usize> {
         self.inner.current_frame_len()
     }
@@ -245,4 +611,22 @@ impl<R> Source for SpyDecoder<R>
     fn total_duration(&self) -> Option<Duration> {
         self.inner.total_duration()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_magic_bytes() {
+        assert!(matches!(sniff(b"ID3\x04\0\0\0\0\0\0\0\0"), Some(MusicFormat::MP3)));
+        assert!(matches!(sniff(&[0xFF, 0xFB, 0x90, 0x64]), Some(MusicFormat::MP3)));
+        assert!(matches!(sniff(b"RIFF\x24\0\0\0WAVEfmt "), Some(MusicFormat::WAV)));
+        assert!(matches!(sniff(b"fLaC\0\0\0\x22"), Some(MusicFormat::FLAC)));
+        assert!(matches!(sniff(b"OggS\0\x02"), Some(MusicFormat::OGG)));
+        // a RIFF that is not WAVE, a JPEG, UTF-16 text and text
+        assert!(sniff(b"RIFF\x24\0\0\0AVI LIST").is_none());
+        assert!(sniff(&[0xFF, 0xD8, 0xFF, 0xE0]).is_none());
+        assert!(sniff(&[0xFF, 0xFE, b'P', 0]).is_none());
+        assert!(sniff(b"PERFORMER \"").is_none());
+    }
+}
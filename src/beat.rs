@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+use crate::settings;
+use crate::spectrum;
+
+const HISTORY: usize = 16; // about one second of distinct taps
+const THRESHOLD: f32 = 1.5;
+const MIN_INTERVAL: f64 = 0.25;
+const INTERVALS: usize = 16;
+// kick drum to snare, where most onsets are
+const LOW_HZ: f32 = 40.0;
+const HIGH_HZ: f32 = 2700.0;
+
+/// Onset detector on spectral flux: the sum of positive magnitude changes between
+/// consecutive taps, compared against an adaptive threshold over the last second.
+pub struct BeatDetector {
+    previous: Vec<f32>,
+    flux_history: VecDeque<f32>,
+    intervals: VecDeque<f64>,
+    last_beat: f64,
+    last_time: f64,
+    last_samples: [i16; settings::SAMPLES],
+    window: Vec<f32>,
+    pub tempo: f32,
+}
+
+impl BeatDetector {
+    pub fn init() -> BeatDetector {
+        let window = hann(settings::SAMPLES);
+        BeatDetector {
+            previous: vec![0.0; spectrum::SPECTRUM_BINS],
+            flux_history: VecDeque::with_capacity(HISTORY),
+            intervals: VecDeque::with_capacity(INTERVALS),
+            last_beat: f64::NEG_INFINITY,
+            last_time: f64::NEG_INFINITY,
+            last_samples: [0; settings::SAMPLES],
+            window,
+            tempo: 0.0,
+        }
+    }
+
+    /// Forgets the history, used when a new track starts or playback jumps.
+    pub fn reset(&mut self) {
+        *self = BeatDetector::init();
+    }
+
+    /// Feeds one tap taken `time` seconds into the track. The tap holds interleaved
+    /// samples of `channels` channels at `rate` Hz, which are mixed down to mono first.
+    /// Returns the onset strength when the tap starts a beat.
+    pub fn process(&mut self, samples: &[i16; settings::SAMPLES], channels: u16, rate: u32, time: f64) -> Option<f32> {
+        if time < self.last_time {
+            self.reset();
+        }
+        self.last_time = time;
+        // The tap is sent more often than the decoder refills it
+        if *samples == self.last_samples {
+            return None;
+        }
+        self.last_samples = *samples;
+
+        // whole frames, as many as fit a power of two for the FFT
+        let channels = (channels as usize).clamp(1, settings::SAMPLES);
+        let size = 1 << (settings::SAMPLES / channels).ilog2();
+        if self.window.len() != size {
+            self.window = hann(size);
+            self.previous = vec![0.0; size / 2];
+        }
+        let mut re: Vec<f32> = samples
+            .chunks_exact(channels)
+            .zip(&self.window)
+            .map(|(frame, w)| frame.iter().map(|&s| s as f32).sum::<f32>() / channels as f32 / 32768.0 * w)
+            .collect();
+        let mut im = vec![0.0; size];
+        spectrum::fft(&mut re, &mut im);
+
+        let bin_hz = rate.max(1) as f32 / size as f32;
+        let low = ((LOW_HZ / bin_hz).round() as usize).max(1);
+        let high = ((HIGH_HZ / bin_hz).round() as usize).clamp(low, size / 2);
+        let mut flux = 0.0;
+        for k in low..high {
+            let magnitude = (re[k] * re[k] + im[k] * im[k]).sqrt();
+            flux += (magnitude - self.previous[k]).max(0.0);
+            self.previous[k] = magnitude;
+        }
+
+        let len = self.flux_history.len().max(1) as f32;
+        let mean = self.flux_history.iter().sum::<f32>() / len;
+        let full = self.flux_history.len() == HISTORY;
+
+        if self.flux_history.len() == HISTORY {
+            self.flux_history.pop_front();
+        }
+        self.flux_history.push_back(flux);
+
+        if !full || flux <= THRESHOLD * mean || flux < 1e-3 || time - self.last_beat < MIN_INTERVAL {
+            return None;
+        }
+
+        let interval = time - self.last_beat;
+        if interval < 2.0 {
+            if self.intervals.len() == INTERVALS {
+                self.intervals.pop_front();
+            }
+            self.intervals.push_back(interval);
+            self.tempo = estimate_tempo(&self.intervals);
+        }
+        self.last_beat = time;
+
+        Some(((flux / mean.max(1e-6) - THRESHOLD) / THRESHOLD).clamp(0.0, 1.0))
+    }
+}
+
+fn hann(size: usize) -> Vec<f32> {
+    (0..size).map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / size as f32).cos()).collect()
+}
+
+/// Median inter-onset interval, folded into the 60 to 180 BPM range.
+fn estimate_tempo(intervals: &VecDeque<f64>) -> f32 {
+    if intervals.len() < 4 {
+        return 0.0;
+    }
+    let mut sorted: Vec<f64> = intervals.iter().copied().collect();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mut bpm = 60.0 / sorted[sorted.len() / 2];
+    while bpm < 60.0 {
+        bpm *= 2.0;
+    }
+    while bpm > 180.0 {
+        bpm /= 2.0;
+    }
+    bpm as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+    const TAP_TIME: f64 = 0.025;
+
+    /// One stereo tap at `time` of a 200 Hz click every half second over quiet noise.
+    fn click_track(time: f64, seed: &mut u32) -> [i16; settings::SAMPLES] {
+        let mut tap = [0; settings::SAMPLES];
+        for (n, frame) in tap.chunks_exact_mut(2).enumerate() {
+            let t = time + n as f64 / RATE as f64;
+            let since = t % 0.5;
+            let click = (2.0 * std::f64::consts::PI * 200.0 * since).sin() * (-since / 0.02).exp() * 20000.0;
+            *seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            let noise = (*seed >> 16) as f64 / 65536.0 * 200.0 - 100.0;
+            frame[0] = (click + noise) as i16;
+            frame[1] = (0.5 * click - noise) as i16;
+        }
+        tap
+    }
+
+    #[test]
+    fn folds_the_tempo_into_range() {
+        let intervals = |i: &[f64]| i.iter().copied().collect::<VecDeque<f64>>();
+        assert_eq!(estimate_tempo(&intervals(&[0.25; 4])), 120.0);
+        assert_eq!(estimate_tempo(&intervals(&[2.0; 4])), 60.0);
+        assert_eq!(estimate_tempo(&intervals(&[0.48, 1.0, 0.5, 0.52, 0.5])), 120.0);
+        assert_eq!(estimate_tempo(&intervals(&[0.5; 3])), 0.0);
+    }
+
+    #[test]
+    fn finds_the_beats_of_a_click_track() {
+        let mut detector = BeatDetector::init();
+        let mut seed = 1;
+        let mut beats = Vec::new();
+        for i in 0..400 {
+            let time = i as f64 * TAP_TIME;
+            if detector.process(&click_track(time, &mut seed), 2, RATE, time).is_some() {
+                beats.push(time);
+            }
+        }
+        assert!(beats.len() >= 15, "{:?}", beats);
+        for pair in beats.windows(2) {
+            assert!((pair[1] - pair[0] - 0.5).abs() < 0.03, "{:?}", beats);
+        }
+        assert!((detector.tempo - 120.0).abs() < 2.0, "{}", detector.tempo);
+    }
+
+    #[test]
+    fn ignores_a_repeated_tap() {
+        let mut detector = BeatDetector::init();
+        let mut seed = 1;
+        let tap = click_track(0.1, &mut seed);
+        detector.process(&tap, 2, RATE, 0.1);
+        assert_eq!(detector.flux_history.len(), 1);
+        assert!(detector.process(&tap, 2, RATE, 0.125).is_none());
+        assert_eq!(detector.flux_history.len(), 1);
+    }
+
+    #[test]
+    fn starts_over_when_time_goes_back() {
+        let mut detector = BeatDetector::init();
+        let mut seed = 1;
+        for i in 0..200 {
+            let time = i as f64 * TAP_TIME;
+            detector.process(&click_track(time, &mut seed), 2, RATE, time);
+        }
+        assert!(detector.tempo > 0.0);
+        detector.process(&click_track(1.0, &mut seed), 2, RATE, 1.0);
+        assert_eq!(detector.flux_history.len(), 1);
+        assert_eq!(detector.tempo, 0.0);
+        assert_eq!(detector.last_beat, f64::NEG_INFINITY);
+    }
+}
@@ -0,0 +1,148 @@
+use std::path::PathBuf;
+use serde::Deserialize;
+
+use crate::config::PlayMode;
+use crate::text;
+use crate::State;
+
+/// What a front end (keys and widgets, MPRIS, the control socket, ...) asks the player to do.
+/// Commands only set the request flags of `State`; the audio thread carries them out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    /// Absolute position in seconds.
+    Seek(f64),
+    /// Seconds forward, or backward when negative.
+    SeekBy(f64),
+    /// 0.0 to 1.0.
+    Volume(f32),
+    /// Index into `State::playlist`.
+    PlayTrack(usize),
+    /// Plays the file after the current track, before going on with the playlist.
+    Enqueue(PathBuf),
+    PlayMode(PlayMode),
+}
+
+impl Command {
+    pub fn apply(self, state: &mut State) {
+        match self {
+            Command::Play | Command::Pause | Command::PlayPause => {
+                state.play = match self {
+                    Command::Play => true,
+                    Command::Pause => false,
+                    _ => !state.play,
+                };
+                if state.play {
+                    state.stopped = false;
+                }
+                let text = if state.play { "playing" } else { "paused" };
+                state.notifications.info(text.to_string());
+            }
+            Command::Stop => {
+                state.play = false;
+                state.stopped = true;
+                state.seek = Some(0.0);
+                state.notifications.info("stopped".to_string());
+            }
+            Command::Next => {
+                if state.play {
+                    state.skip = true;
+                    state.notifications.info("track skipped".to_string());
+                } else {
+                    state.notifications.warning("unpause to skip".to_string());
+                }
+            }
+            Command::Previous => {
+                if state.play {
+                    state.previous = true;
+                    state.notifications.info("previous track".to_string());
+                } else {
+                    state.notifications.warning("unpause to go back".to_string());
+                }
+            }
+            Command::Seek(target) => seek(state, target),
+            Command::SeekBy(offset) => seek(state, state.position + offset),
+            Command::Volume(volume) => {
+                state.volume = volume.clamp(0.0, 1.0);
+                state.notifications.info(format!("volume: {:.0}%", state.volume * 100.0));
+            }
+            Command::PlayTrack(track) => {
+                if track < state.playlist.len() {
+                    state.play_index = Some(track);
+                    state.play = true;
+                    state.stopped = false;
+                    state.notifications.info(format!("playing track {}", track + 1));
+                } else {
+                    state.notifications.warning(format!("no track {}", track + 1));
+                }
+            }
+            Command::Enqueue(path) => {
+                if path.is_file() {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                    state.queue.push(path);
+                    state.notifications.info(format!("queued: {}", name));
+                } else {
+                    state.notifications.warning(format!("enqueue: no file {}", path.display()));
+                }
+            }
+            Command::PlayMode(mode) => {
+                state.play_mode = mode;
+                state.notifications.info(format!("mode: {}", mode.name()));
+            }
+        }
+    }
+}
+
+fn seek(state: &mut State, target: f64) {
+    if state.duration > 0.0 {
+        let target = target.clamp(0.0, state.duration);
+        state.seek = Some(target);
+        state.notifications.info(format!("seek: {}", text::time_string(target)));
+    }
+}
+
+/// A line of the remote control protocol, e.g. `{"command": "seek", "position": 30}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    Seek { position: f64 },
+    SeekBy { offset: f64 },
+    Volume { volume: f32 },
+    PlayTrack { index: usize },
+    Enqueue { path: PathBuf },
+    PlayMode { mode: PlayMode },
+    Status,
+    Subscribe,
+}
+
+impl Request {
+    /// The command to apply, `None` for the requests that only read the status.
+    pub fn command(self) -> Option<Command> {
+        match self {
+            Request::Play => Some(Command::Play),
+            Request::Pause => Some(Command::Pause),
+            Request::PlayPause => Some(Command::PlayPause),
+            Request::Stop => Some(Command::Stop),
+            Request::Next => Some(Command::Next),
+            Request::Previous => Some(Command::Previous),
+            Request::Seek { position } => Some(Command::Seek(position)),
+            Request::SeekBy { offset } => Some(Command::SeekBy(offset)),
+            Request::Volume { volume } => Some(Command::Volume(volume)),
+            Request::PlayTrack { index } => Some(Command::PlayTrack(index)),
+            Request::Enqueue { path } => Some(Command::Enqueue(path)),
+            Request::PlayMode { mode } => Some(Command::PlayMode(mode)),
+            Request::Status | Request::Subscribe => None,
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::settings;
+use crate::theme::Theme;
+
+/// `config.toml` as written on first run, every option at its default.
+const DEFAULT_CONFIG: &str = r##"# small-rust-music-player configuration
+# Options marked (restart) are read at startup, the others apply as soon as the file is saved.
+
+[display]
+# window size in logical pixels (restart)
+width = 512
+height = 512
+# start in fullscreen (restart)
+fullscreen = false
+# frames per second the window and the playback loop aim for, 10 to 500
+frame_rate = 120
+# bars of the built-in visualizer: 4, 8, 16, 32, 64 or 128
+bars = 32
+# theme to start with, by file name in the themes directory without .toml;
+# "default" is default.toml when there is one, the built-in [colors] below otherwise (restart)
+theme = "default"
+
+[playback]
+# folders to play, searched recursively, missing ones skipped; empty plays the folder the player was started in
+music_dirs = []
+# "repeat" the playlist, "repeat_one" track, or play it "once" and pause
+play_mode = "repeat"
+# audio output device by name, empty for the system default (restart)
+output = ""
+# seconds between the samples the visualizers get, 0.005 to 0.5
+sampling_time = 0.025
+# The samples per analysis window (1024) are fixed at build time: user shaders are
+# written against the 512 wide iSpectrum and iWaveform textures they give.
+
+[notifications]
+# desktop notification with the cover art when a new track starts (Linux)
+enabled = true
+
+# The built-in theme, with the same keys as a theme file (fonts included).
+[colors]
+background = "#282828"
+panel = "#d3d0d3"
+text = "#241422"
+active_text = "#2f4476"
+overlay_text = "#d9d9d9"
+accent = "#fec28e"
+visualizer_low = "#0000d9"
+visualizer_high = "#d96c00"
+"##;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayMode {
+    /// Start over after the last track.
+    #[default]
+    Repeat,
+    /// Play the current track again and again.
+    RepeatOne,
+    /// Pause after the last track.
+    Once,
+}
+
+impl PlayMode {
+    pub fn next(self) -> PlayMode {
+        match self {
+            PlayMode::Repeat => PlayMode::RepeatOne,
+            PlayMode::RepeatOne => PlayMode::Once,
+            PlayMode::Once => PlayMode::Repeat,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PlayMode::Repeat => "repeat",
+            PlayMode::RepeatOne => "repeat one",
+            PlayMode::Once => "once",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Display {
+    pub width: i32,
+    pub height: i32,
+    pub fullscreen: bool,
+    pub frame_rate: f64,
+    pub bars: usize,
+    pub theme: String,
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display {
+            width: 512,
+            height: 512,
+            fullscreen: false,
+            frame_rate: 120.0,
+            bars: 32,
+            theme: "default".to_string(),
+        }
+    }
+}
+
+impl Display {
+    /// Seconds per frame.
+    pub fn frame_time(&self) -> f64 {
+        1.0 / self.frame_rate
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Playback {
+    pub music_dirs: Vec<PathBuf>,
+    pub play_mode: PlayMode,
+    pub output: String,
+    pub sampling_time: f64,
+}
+
+impl Default for Playback {
+    fn default() -> Playback {
+        Playback {
+            music_dirs: Vec::new(),
+            play_mode: PlayMode::Repeat,
+            output: String::new(),
+            sampling_time: 0.025,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Notifications {
+    pub enabled: bool,
+}
+
+impl Default for Notifications {
+    fn default() -> Notifications {
+        Notifications { enabled: true }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub display: Display,
+    pub playback: Playback,
+    pub notifications: Notifications,
+    pub colors: Theme,
+}
+
+impl Config {
+    /// Reads `config.toml`, writing the defaults there first when there is none yet.
+    /// A file that does not parse or validate gives the defaults and the reason.
+    pub fn load() -> (Config, Option<String>) {
+        let path = config_path();
+        if !path.exists() {
+            let written = fs::create_dir_all(settings::config_dir())
+                .and_then(|_| fs::write(&path, DEFAULT_CONFIG));
+            return match written {
+                Ok(()) => (Config::default(), None),
+                Err(error) => (Config::default(), Some(format!("{}: {}", path.display(), error))),
+            };
+        }
+        match Config::read(&path) {
+            Ok(config) => (config, None),
+            Err(error) => (Config::default(), Some(error)),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Config, String> {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", file_name, e))?;
+        let mut config: Config = toml::from_str(&text).map_err(|e| match e.span() {
+            Some(span) => format!("{} line {}: {}", file_name, text[..span.start].matches('\n').count() + 1, e.message()),
+            None => format!("{}: {}", file_name, e.message()),
+        })?;
+        config.check().map_err(|e| format!("{}: {}", file_name, e))?;
+        config.playback.music_dirs = config.playback.music_dirs.iter().map(|dir| expand_home(dir)).collect();
+        Ok(config)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let display = &self.display;
+        if !(128..=8192).contains(&display.width) || !(128..=8192).contains(&display.height) {
+            return Err("display.width and display.height must be between 128 and 8192".to_string());
+        }
+        if !(10.0..=500.0).contains(&display.frame_rate) {
+            return Err("display.frame_rate must be between 10 and 500".to_string());
+        }
+        if !display.bars.is_power_of_two() || !(4..=settings::MAX_BARS).contains(&display.bars) {
+            return Err(format!("display.bars must be a power of two from 4 to {}", settings::MAX_BARS));
+        }
+        if display.theme.is_empty() {
+            return Err("display.theme must name a theme, \"default\" for the built-in one".to_string());
+        }
+        // a missing music folder, like an unplugged drive, is skipped by the playback thread
+        let playback = &self.playback;
+        if !(0.005..=0.5).contains(&playback.sampling_time) {
+            return Err("playback.sampling_time must be between 0.005 and 0.5".to_string());
+        }
+        if !(0.5..=3.0).contains(&self.colors.font_scale) {
+            return Err("colors.font_scale must be between 0.5 and 3.0".to_string());
+        }
+        Ok(())
+    }
+
+    /// The options read only at startup that differ between `self` and `other`.
+    pub fn restart_changes(&self, other: &Config) -> Vec<&'static str> {
+        let mut changes = Vec::new();
+        if self.display.width != other.display.width || self.display.height != other.display.height {
+            changes.push("window size");
+        }
+        if self.display.fullscreen != other.display.fullscreen {
+            changes.push("fullscreen");
+        }
+        if self.display.theme != other.display.theme {
+            changes.push("theme");
+        }
+        if self.playback.output != other.playback.output {
+            changes.push("output");
+        }
+        changes
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    settings::config_dir().join("config.toml")
+}
+
+/// `~/Music` and the like.
+fn expand_home(dir: &Path) -> PathBuf {
+    match dir.strip_prefix("~") {
+        Ok(rest) => PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(rest),
+        Err(_) => dir.to_path_buf(),
+    }
+}
+
+/// Watches `config.toml` for changes.
+pub struct Watch {
+    modified: Option<SystemTime>,
+    last_check: f64,
+}
+
+impl Watch {
+    pub fn init() -> Watch {
+        Watch {
+            modified: modified(&config_path()),
+            last_check: 0.0,
+        }
+    }
+
+    /// Checks the file every `settings::CONFIG_POLL_TIME` seconds. Returns `Some` when it
+    /// changed, holding the new configuration or why it was rejected.
+    pub fn poll(&mut self, now: f64) -> Option<Result<Config, String>> {
+        if now - self.last_check < settings::CONFIG_POLL_TIME {
+            return None;
+        }
+        self.last_check = now;
+        let path = config_path();
+        let stamp = modified(&path);
+        if stamp.is_none() || stamp == self.modified {
+            return None;
+        }
+        self.modified = stamp;
+        Some(Config::read(&path))
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_of(text: &str) -> String {
+        let path = std::env::temp_dir().join(format!("srmp-config-{}.toml", std::process::id()));
+        fs::write(&path, text).unwrap();
+        let error = Config::read(&path).err().unwrap();
+        let _ = fs::remove_file(&path);
+        error
+    }
+
+    #[test]
+    fn errors_name_their_line() {
+        assert!(error_of("enabled = \"yes\"").contains(" line 1: "));
+        assert!(error_of("[notifications]\nenabled = \"yes\"\n").contains(" line 2: "));
+        // an error at the very start of a line is on that line, not the one before
+        assert!(error_of("[notifications]\nenabled = true\nenabled = false\n").contains(" line 3: "));
+    }
+
+    #[test]
+    fn keeps_missing_music_folders() {
+        let path = std::env::temp_dir().join(format!("srmp-config-dirs-{}.toml", std::process::id()));
+        fs::write(&path, "[playback]\nmusic_dirs = [\"/no/such/drive/Music\"]\n").unwrap();
+        let config = Config::read(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(config.unwrap().playback.music_dirs, vec![PathBuf::from("/no/such/drive/Music")]);
+    }
+}
@@ -0,0 +1,312 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::{json, Value};
+
+use crate::command::Request;
+use crate::settings;
+use crate::status::Status;
+use crate::State;
+
+type Subscribers = Arc<Mutex<Vec<UnixStream>>>;
+
+/// Whether this player owns the socket, and so removes it on quit.
+static BOUND: AtomicBool = AtomicBool::new(false);
+
+/// Listens on `settings::socket_path()` for newline-delimited JSON requests, one thread per
+/// client, and pushes events to the clients that subscribed. When another player already
+/// owns the socket this one goes without and says so in a notification.
+pub fn spawn(state: Arc<Mutex<State>>) {
+    thread::spawn(move || {
+        if let Err(error) = serve(Arc::clone(&state), &settings::socket_path()) {
+            state.lock().unwrap().notifications.warning(format!("control: {}", error));
+        }
+    });
+}
+
+fn serve(state: Arc<Mutex<State>>, path: &Path) -> io::Result<()> {
+    if UnixStream::connect(path).is_ok() {
+        let text = format!("{} is in use by another player", path.display());
+        return Err(io::Error::new(io::ErrorKind::AddrInUse, text));
+    }
+    // left behind by a player that did not shut down
+    let _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    BOUND.store(true, Ordering::SeqCst);
+
+    let subscribers: Subscribers = Arc::new(Mutex::new(Vec::new()));
+    let state_events = Arc::clone(&state);
+    let subscribers_events = Arc::clone(&subscribers);
+    thread::spawn(move || publish(state_events, subscribers_events));
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let state = Arc::clone(&state);
+        let subscribers = Arc::clone(&subscribers);
+        thread::spawn(move || handle(state, subscribers, stream));
+    }
+    Ok(())
+}
+
+/// Answers every request line of one client with one reply line. A dropped
+/// connection only ends this client.
+fn handle(state: Arc<Mutex<State>>, subscribers: Subscribers, stream: UnixStream) -> io::Result<()> {
+    let reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Request>(&line) {
+            Ok(Request::Status) => {
+                let status = Status::of(&state.lock().unwrap());
+                json!({ "ok": true, "status": status })
+            }
+            Ok(Request::Subscribe) => {
+                let subscriber = writer.try_clone()?;
+                subscriber.set_write_timeout(Some(Duration::from_secs_f64(settings::CONTROL_WRITE_TIMEOUT)))?;
+                subscribers.lock().unwrap().push(subscriber);
+                json!({ "ok": true })
+            }
+            Ok(request) => {
+                if let Some(command) = request.command() {
+                    command.apply(&mut state.lock().unwrap());
+                }
+                json!({ "ok": true })
+            }
+            Err(error) => json!({ "ok": false, "error": error.to_string() }),
+        };
+        send(&mut writer, &reply)?;
+    }
+    Ok(())
+}
+
+/// Polls `State` every `settings::CONTROL_POLL_TIME` seconds and sends
+/// `{"event": ..., "status": ...}` lines to the subscribers, dropping those that hung up
+/// or fall `settings::CONTROL_WRITE_TIMEOUT` seconds behind.
+fn publish(state: Arc<Mutex<State>>, subscribers: Subscribers) {
+    let mut last = Status::of(&state.lock().unwrap());
+    let mut last_time = Instant::now();
+    loop {
+        thread::sleep(Duration::from_secs_f64(settings::CONTROL_POLL_TIME));
+        let now = Status::of(&state.lock().unwrap());
+        let events = now.events(&last, last_time.elapsed().as_secs_f64());
+        if !events.is_empty() {
+            // written outside the lock, so a slow subscriber does not hold up a new one
+            let mut streams = std::mem::take(&mut *subscribers.lock().unwrap());
+            for event in events {
+                let line = json!({ "event": event, "status": now });
+                streams.retain_mut(|stream| {
+                    let sent = send(stream, &line).is_ok();
+                    if !sent {
+                        // part of the line may have gone out, so end the connection there
+                        let _ = stream.shutdown(Shutdown::Both);
+                    }
+                    sent
+                });
+            }
+            subscribers.lock().unwrap().append(&mut streams);
+        }
+        last = now;
+        last_time = Instant::now();
+    }
+}
+
+/// Removes the socket when this player owns it, for a clean shutdown.
+pub fn remove_socket() {
+    if BOUND.load(Ordering::SeqCst) {
+        let _ = fs::remove_file(settings::socket_path());
+    }
+}
+
+/// One JSON value and its newline in a single write, so replies and events
+/// going out on the same connection do not interleave.
+fn send(stream: &mut UnixStream, value: &Value) -> io::Result<()> {
+    stream.write_all(format!("{}\n", value).as_bytes())
+}
+
+const USAGE: &str = "usage: small-rust-music-player ctl <command>
+
+commands:
+  play | pause | play-pause | stop | next | previous
+  seek <seconds>        jump to a position; +N or -N seeks relative to it
+  volume <percent>      0 to 100
+  mode <mode>           repeat, repeat-one or once
+  track <number>        play the numbered playlist entry
+  enqueue <file>...     play the files after the current track
+  status                print the player status as JSON
+  events                print state changes as they happen, one JSON object per line";
+
+/// `small-rust-music-player ctl ...`: sends one request to a running player
+/// and prints its answer. Returns the process exit code.
+pub fn client(args: &[String]) -> i32 {
+    let requests = match requests(args) {
+        Ok(requests) => requests,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 2;
+        }
+    };
+    let path = settings::socket_path();
+    match talk(&path, &requests) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(error) => {
+            eprintln!("{}: {}", path.display(), error);
+            1
+        }
+    }
+}
+
+fn requests(args: &[String]) -> Result<Vec<Value>, String> {
+    let command = args.first().map(String::as_str).unwrap_or_default();
+    let argument = args.get(1).map(String::as_str);
+    let number = |what: &str| -> Result<f64, String> {
+        let text = argument.ok_or(format!("{} needs {}", command, what))?;
+        text.parse::<f64>().ok().filter(|n| n.is_finite()).ok_or(format!("{} is not a number", text))
+    };
+    let request = match command {
+        "play" | "pause" | "stop" | "next" | "previous" | "status" => json!({ "command": command }),
+        "play-pause" => json!({ "command": "play_pause" }),
+        "events" => json!({ "command": "subscribe" }),
+        "seek" => {
+            let seconds = number("seconds")?;
+            if argument.is_some_and(|a| a.starts_with('+') || a.starts_with('-')) {
+                json!({ "command": "seek_by", "offset": seconds })
+            } else {
+                json!({ "command": "seek", "position": seconds })
+            }
+        }
+        "volume" => {
+            let percent = number("a percentage")?;
+            if !(0.0..=100.0).contains(&percent) {
+                return Err(format!("volume {} is not between 0 and 100", percent));
+            }
+            json!({ "command": "volume", "volume": percent / 100.0 })
+        }
+        "mode" => match argument {
+            Some(mode @ ("repeat" | "repeat-one" | "once")) => json!({ "command": "play_mode", "mode": mode.replace('-', "_") }),
+            _ => return Err("mode needs repeat, repeat-one or once".to_string()),
+        },
+        "track" => {
+            let text = argument.ok_or("track needs a track number")?;
+            match text.parse::<usize>() {
+                Ok(track) if track >= 1 => json!({ "command": "play_track", "index": track - 1 }),
+                _ => return Err(format!("no track {}", text)),
+            }
+        }
+        "enqueue" => {
+            if args.len() < 2 {
+                return Err("enqueue needs files".to_string());
+            }
+            // the player runs elsewhere, so it gets absolute paths
+            let paths: Vec<PathBuf> = args[1..].iter()
+                .map(|arg| Path::new(arg).canonicalize().unwrap_or_else(|_| PathBuf::from(arg)))
+                .collect();
+            return Ok(paths.iter().map(|path| json!({ "command": "enqueue", "path": path })).collect());
+        }
+        "" | "help" | "-h" | "--help" => return Err(USAGE.to_string()),
+        _ => return Err(format!("unknown command {}\n\n{}", command, USAGE)),
+    };
+    Ok(vec![request])
+}
+
+/// Sends the requests and prints the replies; after a subscription keeps printing
+/// events until the player goes away. `Ok(false)` if the player refused a request.
+fn talk(path: &Path, requests: &[Value]) -> io::Result<bool> {
+    let mut stream = UnixStream::connect(path)?;
+    let mut lines = BufReader::new(stream.try_clone()?).lines();
+    let mut ok = true;
+    for request in requests {
+        send(&mut stream, request)?;
+        let line = lines.next().ok_or(io::ErrorKind::UnexpectedEof)??;
+        let reply: Value = serde_json::from_str(&line).map_err(io::Error::other)?;
+        if reply["ok"] != true {
+            eprintln!("{}", reply["error"].as_str().unwrap_or(&line));
+            ok = false;
+        } else if let Some(status) = reply.get("status") {
+            println!("{}", serde_json::to_string_pretty(status).map_err(io::Error::other)?);
+        }
+    }
+    if requests.iter().any(|request| request["command"] == "subscribe") {
+        for line in lines {
+            println!("{}", line?);
+        }
+    }
+    Ok(ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+    use crate::config::PlayMode;
+
+    fn request(args: &[&str]) -> Result<Vec<Value>, String> {
+        requests(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>())
+    }
+
+    fn command(line: &str) -> Result<Option<Command>, String> {
+        serde_json::from_str::<Request>(line).map(Request::command).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn parses_ctl_arguments() {
+        assert_eq!(request(&["play-pause"]).unwrap(), vec![json!({ "command": "play_pause" })]);
+        assert_eq!(request(&["events"]).unwrap(), vec![json!({ "command": "subscribe" })]);
+        assert_eq!(request(&["seek", "90"]).unwrap(), vec![json!({ "command": "seek", "position": 90.0 })]);
+        assert_eq!(request(&["seek", "+10"]).unwrap(), vec![json!({ "command": "seek_by", "offset": 10.0 })]);
+        assert_eq!(request(&["seek", "-5.5"]).unwrap(), vec![json!({ "command": "seek_by", "offset": -5.5 })]);
+        assert_eq!(request(&["volume", "40"]).unwrap(), vec![json!({ "command": "volume", "volume": 0.4 })]);
+        assert_eq!(request(&["mode", "repeat-one"]).unwrap(), vec![json!({ "command": "play_mode", "mode": "repeat_one" })]);
+        assert_eq!(request(&["track", "3"]).unwrap(), vec![json!({ "command": "play_track", "index": 2 })]);
+        assert_eq!(
+            request(&["enqueue", "/no/such/a.mp3", "/no/such/b.mp3"]).unwrap(),
+            vec![json!({ "command": "enqueue", "path": "/no/such/a.mp3" }), json!({ "command": "enqueue", "path": "/no/such/b.mp3" })],
+        );
+    }
+
+    #[test]
+    fn refuses_bad_ctl_arguments() {
+        for args in [
+            &["seek"][..], &["seek", "soon"], &["seek", "NaN"], &["seek", "+inf"],
+            &["volume", "101"], &["volume", "-1"], &["volume", "loud"],
+            &["mode"], &["mode", "shuffle"],
+            &["track", "0"], &["track", "-2"], &["track", "1.5"],
+            &["enqueue"], &["rewind"], &[],
+        ] {
+            assert!(request(args).is_err(), "{:?} was accepted", args);
+        }
+        assert!(request(&["frobnicate"]).unwrap_err().starts_with("unknown command frobnicate"));
+    }
+
+    #[test]
+    fn parses_protocol_lines() {
+        assert_eq!(command(r#"{"command": "seek_by", "offset": -5}"#), Ok(Some(Command::SeekBy(-5.0))));
+        assert_eq!(command(r#"{"command": "volume", "volume": 0.25}"#), Ok(Some(Command::Volume(0.25))));
+        assert_eq!(command(r#"{"command": "play_mode", "mode": "once"}"#), Ok(Some(Command::PlayMode(PlayMode::Once))));
+        assert_eq!(command(r#"{"command": "status"}"#), Ok(None));
+        // out of range volumes are taken and clamped when applied
+        assert_eq!(command(r#"{"command": "volume", "volume": 7}"#), Ok(Some(Command::Volume(7.0))));
+
+        for line in [
+            "play",
+            "{\"command\": \"play\"",
+            r#"{"cmd": "play"}"#,
+            r#"{"command": "explode"}"#,
+            r#"{"command": "seek"}"#,
+            r#"{"command": "seek", "position": "soon"}"#,
+            r#"{"command": "play_track", "index": -1}"#,
+            r#"{"command": "play_mode", "mode": "shuffle"}"#,
+        ] {
+            assert!(command(line).is_err(), "{} was accepted", line);
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use ab_glyph::{point, Font, FontArc, FontVec, GlyphId, PxScale, ScaleFont};
+use image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::assets;
+use crate::mesh::TextureUV;
+use crate::text;
+
+/// Starting side of the atlas; it doubles up to `MAX_ATLAS_SIZE` when one frame needs more.
+pub const ATLAS_SIZE: u32 = 1024;
+const MAX_ATLAS_SIZE: u32 = 4096;
+const PANEL_SIZE: u32 = 128;
+const PANEL_EDGE: u32 = 3;
+const PADDING: u32 = 1;
+
+// Wide-coverage fonts commonly shipped with the OS, loaded only when
+// a character is missing from every font loaded so far
+const SYSTEM_FALLBACKS: &[&str] = &[
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/droid/DroidSansFallbackFull.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\msgothic.ttc",
+    "C:\\Windows\\Fonts\\malgun.ttf",
+    "C:\\Windows\\Fonts\\seguisym.ttf",
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    "/Library/Fonts/Arial Unicode.ttf",
+];
+
+/// A rasterized glyph: where it sits in the atlas and how to place it
+/// relative to the pen position on the baseline.
+#[derive(Debug, Clone, Copy)]
+pub struct Glyph {
+    pub uv: TextureUV,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub advance: f32,
+    id: GlyphId,
+    font: usize,
+}
+
+/// A font further down the chain, read only once a character needs it.
+enum Fallback {
+    File(PathBuf),
+    Loaded(FontArc),
+}
+
+/// Font chain with a glyph cache backed by one RGBA atlas texture.
+/// Glyph pixels are black with coverage in alpha, as the GUI shader tints them.
+pub struct Fonts {
+    fonts: Vec<FontArc>,
+    pending: Vec<Fallback>,
+    cache: HashMap<(char, u32), Glyph>,
+    pub atlas: RgbaImage,
+    pub dirty: bool,
+    /// A glyph did not fit; the atlas is emptied before the next frame.
+    full: bool,
+    /// The atlas was emptied before this frame, so all it holds is this frame's glyphs.
+    fresh: bool,
+    shelf_x: u32,
+    shelf_y: u32,
+    shelf_height: u32,
+}
+
+impl Fonts {
+    /// Uses `primary` (the built-in monospace font when `None`) followed by `fallbacks`,
+    /// the built-in font and the system fallbacks. Returns the fonts that could not be used.
+    pub fn load(primary: Option<&Path>, fallbacks: &[PathBuf]) -> (Fonts, Vec<String>) {
+        let builtin = assets::Ass::load().font;
+        let mut errors = Vec::new();
+        let mut fonts = Vec::new();
+
+        if let Some(path) = primary {
+            match load_font(path) {
+                Ok(font) => fonts.push(font),
+                Err(error) => errors.push(error),
+            }
+        }
+
+        let mut pending = Vec::new();
+        for path in fallbacks {
+            if path.is_file() {
+                pending.push(Fallback::File(path.clone()));
+            } else {
+                errors.push(format!("{}: not found", path.display()));
+            }
+        }
+        // the built-in font is the primary one unless another was given
+        if fonts.is_empty() {
+            fonts.push(builtin);
+        } else {
+            pending.push(Fallback::Loaded(builtin));
+        }
+        pending.extend(SYSTEM_FALLBACKS.iter().map(PathBuf::from).filter(|p| p.is_file()).map(Fallback::File));
+
+        let mut fonts = Fonts {
+            fonts,
+            pending,
+            cache: HashMap::new(),
+            atlas: RgbaImage::new(ATLAS_SIZE, ATLAS_SIZE),
+            dirty: true,
+            full: false,
+            fresh: true,
+            shelf_x: 0,
+            shelf_y: 0,
+            shelf_height: 0,
+        };
+        fonts.clear();
+        (fonts, errors)
+    }
+
+    /// Empties the atlas and the cache, keeping only the panel box.
+    fn clear(&mut self) {
+        self.cache.clear();
+        for (x, y, pixel) in self.atlas.enumerate_pixels_mut() {
+            *pixel = Rgba([0, 0, 0, 0]);
+            if x < PANEL_SIZE && y < PANEL_SIZE {
+                let v = if (PANEL_EDGE..PANEL_SIZE - PANEL_EDGE).contains(&y) { 204 } else { 140 };
+                *pixel = Rgba([v, v, v, 255]);
+            }
+        }
+        // glyphs fill the rest of the first shelf, right of the panel box
+        self.shelf_x = PANEL_SIZE + PADDING;
+        self.shelf_y = 0;
+        self.shelf_height = PANEL_SIZE;
+        self.dirty = true;
+        self.full = false;
+    }
+
+    /// Side of the atlas in pixels.
+    pub fn size(&self) -> u32 {
+        self.atlas.width()
+    }
+
+    /// Empties the atlas when a glyph of the last frame did not fit. Called before
+    /// anything is laid out, so the glyphs of one frame never point into a cleared atlas.
+    /// When the frame overflowed an atlas emptied just before it, its glyphs alone need
+    /// more room, so the atlas doubles; at its largest it is kept as it is rather than
+    /// emptied again for the same glyphs, and what does not fit stays blank.
+    pub fn next_frame(&mut self) {
+        if !self.full {
+            self.fresh = false;
+            return;
+        }
+        if self.fresh {
+            if self.size() >= MAX_ATLAS_SIZE {
+                self.full = false;
+                return;
+            }
+            let size = self.size() * 2;
+            self.atlas = RgbaImage::new(size, size);
+        }
+        self.clear();
+        self.fresh = true;
+    }
+
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let size = self.size();
+        if self.shelf_x + width > size {
+            self.shelf_x = 0;
+            self.shelf_y += self.shelf_height + PADDING;
+            self.shelf_height = 0;
+        }
+        if self.shelf_y + height > size || width > size {
+            return None;
+        }
+        let place = (self.shelf_x, self.shelf_y);
+        self.shelf_x += width + PADDING;
+        self.shelf_height = self.shelf_height.max(height);
+        Some(place)
+    }
+
+    /// First font of the chain that has `c`, loading pending fallbacks as needed.
+    /// Falls back to the missing-glyph box of the primary font.
+    fn find(&mut self, c: char) -> (usize, GlyphId) {
+        for (index, font) in self.fonts.iter().enumerate() {
+            let id = font.glyph_id(c);
+            if id.0 != 0 {
+                return (index, id);
+            }
+        }
+        while !self.pending.is_empty() {
+            let font = match self.pending.remove(0) {
+                Fallback::Loaded(font) => font,
+                Fallback::File(path) => match load_font(&path) {
+                    Ok(font) => font,
+                    Err(_) => continue,
+                },
+            };
+            let id = font.glyph_id(c);
+            self.fonts.push(font);
+            if id.0 != 0 {
+                return (self.fonts.len() - 1, id);
+            }
+        }
+        (0, GlyphId(0))
+    }
+
+    pub fn ascent(&self, scale: f32) -> f32 {
+        self.fonts[0].as_scaled(px_scale(scale)).ascent()
+    }
+
+    pub fn glyph(&mut self, c: char, scale: f32) -> Glyph {
+        let key = (c, scale.to_bits());
+        if let Some(glyph) = self.cache.get(&key) {
+            return *glyph;
+        }
+
+        let (index, id) = self.find(c);
+        let font = self.fonts[index].clone();
+        let px = px_scale(scale);
+        let mut glyph = Glyph {
+            uv: TextureUV { u1: 0.0, u2: 0.0, v1: 0.0, v2: 0.0 },
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            advance: font.as_scaled(px).h_advance(id),
+            id,
+            font: index,
+        };
+
+        if let Some(outlined) = font.outline_glyph(id.with_scale_and_position(px, point(0.0, 0.0))) {
+            let bounds = outlined.px_bounds();
+            let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+            let Some((ax, ay)) = self.allocate(width, height) else {
+                // left blank and out of the cache, it is drawn once the atlas has been emptied
+                self.full = true;
+                return glyph;
+            };
+            let atlas = &mut self.atlas;
+            outlined.draw(|x, y, c| {
+                atlas.put_pixel(ax + x, ay + y, Rgba([0, 0, 0, (c.clamp(0.0, 1.0) * 255.0) as u8]));
+            });
+            glyph.uv = TextureUV {
+                u1: ax as f32,
+                u2: (ax + width) as f32,
+                v1: ay as f32,
+                v2: (ay + height) as f32,
+            };
+            glyph.uv.normalize(self.size() as f32, self.size() as f32);
+            glyph.x = bounds.min.x;
+            glyph.y = bounds.min.y;
+            glyph.width = width as f32;
+            glyph.height = height as f32;
+            self.dirty = true;
+        }
+
+        self.cache.insert(key, glyph);
+        glyph
+    }
+
+    /// Pen position of every glyph of `text`, kerned when neighbours share a font.
+    pub fn layout(&mut self, text: &str, scale: f32) -> Vec<(f32, Glyph)> {
+        let mut placed = Vec::new();
+        let mut pen = 0.0;
+        let mut previous: Option<Glyph> = None;
+        for c in text.chars() {
+            let glyph = self.glyph(c, scale);
+            if let Some(prev) = previous {
+                if prev.font == glyph.font {
+                    pen += self.fonts[glyph.font].as_scaled(px_scale(scale)).kern(prev.id, glyph.id);
+                }
+            }
+            placed.push((pen, glyph));
+            pen += glyph.advance;
+            previous = Some(glyph);
+        }
+        placed
+    }
+
+    pub fn text_width(&mut self, text: &str, scale: f32) -> f32 {
+        match self.layout(text, scale).last() {
+            Some((pen, glyph)) => pen + glyph.advance,
+            None => 0.0,
+        }
+    }
+
+    /// Texture coordinates of the panel box, the gray square at the top left of the atlas.
+    pub fn panel_uv(&self) -> TextureUV {
+        let mut uv = TextureUV {
+            u1: 0.0,
+            u2: PANEL_SIZE as f32,
+            v1: 0.0,
+            v2: PANEL_SIZE as f32,
+        };
+        uv.normalize(self.size() as f32, self.size() as f32);
+        uv
+    }
+}
+
+/// The line height `text::HEIGHT` spans the ascent and descent of the font.
+fn px_scale(scale: f32) -> PxScale {
+    PxScale::from(text::HEIGHT * scale)
+}
+
+fn load_font(path: &Path) -> Result<FontArc, String> {
+    let data = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let is_collection = path.extension().is_some_and(|x| x.eq_ignore_ascii_case("ttc"));
+    let font = if is_collection {
+        FontVec::try_from_vec_and_index(data, 0)
+    } else {
+        FontVec::try_from_vec(data)
+    };
+    font.map(FontArc::new).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grows_when_one_frame_overflows() {
+        let (mut fonts, _) = Fonts::load(None, &[]);
+        let text: String = ('!'..='~').collect();
+        // glyphs of 200 px, far more than 1024² holds
+        let scale = 10.0;
+        fonts.layout(&text, scale);
+        assert!(fonts.full);
+
+        fonts.next_frame();
+        assert!(fonts.size() > ATLAS_SIZE);
+        let placed = fonts.layout(&text, scale);
+        assert!(!fonts.full);
+        assert!(placed.iter().all(|(_, glyph)| glyph.width > 0.0 && glyph.uv.u2 > glyph.uv.u1));
+
+        // the glyphs stay cached, nothing is rasterized again
+        fonts.dirty = false;
+        fonts.next_frame();
+        fonts.layout(&text, scale);
+        assert!(!fonts.dirty);
+    }
+
+    #[test]
+    fn never_clears_twice_for_the_same_glyphs() {
+        let (mut fonts, _) = Fonts::load(None, &[]);
+        let text: String = ('!'..='~').collect();
+        // too big even for the largest atlas
+        let scale = 60.0;
+        for _ in 0..4 {
+            fonts.layout(&text, scale);
+            fonts.next_frame();
+        }
+        assert_eq!(fonts.size(), MAX_ATLAS_SIZE);
+        fonts.dirty = false;
+        fonts.layout(&text, scale);
+        fonts.next_frame();
+        fonts.layout(&text, scale);
+        assert!(!fonts.dirty);
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+
+use crate::settings;
+use crate::tags::Tags;
+
+/// One track played, from start to wherever it was left. The playback loop emits it
+/// when a track ends; the history and the scrobble log are written from it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Play {
+    /// Start, in seconds since the Unix epoch.
+    pub time: u64,
+    pub path: PathBuf,
+    pub artist: String,
+    pub title: String,
+    pub album: String,
+    /// Track number as tagged.
+    #[serde(default)]
+    pub track: String,
+    /// Length of the track in seconds, 0 when unknown.
+    pub duration: f64,
+    /// Seconds actually played, pauses and seeks left out.
+    pub listened: f64,
+    /// Played to its end, rather than skipped or left for another track.
+    pub completed: bool,
+}
+
+impl Play {
+    pub fn new(path: &Path, tags: Tags, started: SystemTime, duration: f64, listened: f64, completed: bool) -> Play {
+        Play {
+            time: started.duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs()),
+            path: path.to_path_buf(),
+            artist: tags.artist,
+            title: tags.title,
+            album: tags.album,
+            track: tags.track,
+            duration,
+            listened,
+            completed,
+        }
+    }
+
+    /// `Artist - Title`, falling back to the file name.
+    pub fn name(&self) -> String {
+        match (self.artist.is_empty(), self.title.is_empty()) {
+            (_, true) => self.path.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+            (true, false) => self.title.clone(),
+            (false, false) => format!("{} - {}", self.artist, self.title),
+        }
+    }
+}
+
+/// Every play so far, one JSON object per line, in the data directory.
+pub fn db_path() -> PathBuf {
+    settings::data_dir().join("history.jsonl")
+}
+
+/// Appends `play` as a line of its own, also after a last line cut short by a crash.
+pub fn record(db: &Path, play: &Play) -> io::Result<()> {
+    if let Some(dir) = db.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let line = serde_json::to_string(play).map_err(io::Error::other)?;
+    let mut file = OpenOptions::new().create(true).read(true).append(true).open(db)?;
+    let mut last = [b'\n'];
+    if file.metadata()?.len() > 0 {
+        file.seek(SeekFrom::End(-1))?;
+        file.read_exact(&mut last)?;
+    }
+    let start = if last[0] == b'\n' { "" } else { "\n" };
+    file.write_all(format!("{}{}\n", start, line).as_bytes())
+}
+
+/// All plays, oldest first. No history yet is an empty one; lines that do not
+/// parse, like one cut short by a crash, are skipped.
+pub fn load(db: &Path) -> io::Result<Vec<Play>> {
+    let text = match fs::read_to_string(db) {
+        Ok(text) => text,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+    Ok(text.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+/// What the history says about one track.
+#[derive(Debug, Clone)]
+pub struct TrackStats {
+    pub name: String,
+    pub plays: usize,
+    pub skips: usize,
+    pub listened: f64,
+    pub last_played: u64,
+}
+
+/// Per track statistics, the most played first.
+pub fn tracks(plays: &[Play]) -> Vec<TrackStats> {
+    let mut by_path: HashMap<&Path, TrackStats> = HashMap::new();
+    for play in plays {
+        let stats = by_path.entry(&play.path).or_insert_with(|| TrackStats {
+            name: String::new(),
+            plays: 0,
+            skips: 0,
+            listened: 0.0,
+            last_played: 0,
+        });
+        // the latest tags win
+        stats.name = play.name();
+        if play.completed {
+            stats.plays += 1;
+        } else {
+            stats.skips += 1;
+        }
+        stats.listened += play.listened;
+        stats.last_played = stats.last_played.max(play.time);
+    }
+    let mut tracks: Vec<TrackStats> = by_path.into_values().collect();
+    tracks.sort_by(|a, b| b.plays.cmp(&a.plays).then(b.listened.total_cmp(&a.listened)).then_with(|| a.name.cmp(&b.name)));
+    tracks
+}
+
+/// Lines for the stats screen and `small-rust-music-player stats`: a summary,
+/// then the `count` most played and `count` most recent tracks. Either list is left
+/// out when `count` for it is 0.
+pub fn report(plays: &[Play], top: usize, recent: usize) -> Vec<String> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+    let listened: f64 = plays.iter().map(|p| p.listened).sum();
+    let skips = plays.iter().filter(|p| !p.completed).count();
+    let mut lines = vec![format!(
+        "{} plays, {} skipped, {} h {:02} min listened",
+        plays.len(),
+        skips,
+        listened as u64 / 3600,
+        listened as u64 / 60 % 60
+    )];
+    if top > 0 {
+        lines.push(String::new());
+        lines.push("Most played".to_string());
+        for track in tracks(plays).iter().filter(|t| t.plays > 0).take(top) {
+            let skips = if track.skips > 0 { format!(", {} skipped", track.skips) } else { String::new() };
+            lines.push(format!("{:>4}×  {}  ({}{})", track.plays, track.name, ago(now, track.last_played), skips));
+        }
+    }
+    if recent > 0 {
+        lines.push(String::new());
+        lines.push("Recently played".to_string());
+        for play in plays.iter().rev().take(recent) {
+            let how = if play.completed { "" } else { ", skipped" };
+            lines.push(format!("{:>10}  {}{}", ago(now, play.time), play.name(), how));
+        }
+    }
+    lines
+}
+
+fn ago(now: u64, time: u64) -> String {
+    let seconds = now.saturating_sub(time);
+    if seconds < 60 {
+        "just now".to_string()
+    } else if seconds < 3600 {
+        format!("{} min ago", seconds / 60)
+    } else if seconds < 86400 {
+        format!("{} h ago", seconds / 3600)
+    } else {
+        format!("{} d ago", seconds / 86400)
+    }
+}
+
+/// `small-rust-music-player stats [top|recent] [count]`: prints the report.
+/// Returns the process exit code.
+pub fn cli(args: &[String]) -> i32 {
+    let (top, recent) = match args.first().map(String::as_str) {
+        None => (true, true),
+        Some("top") => (true, false),
+        Some("recent") => (false, true),
+        Some(_) => {
+            eprintln!("usage: small-rust-music-player stats [top|recent] [count]");
+            return 2;
+        }
+    };
+    let count = match args.get(1).map(|n| n.parse::<usize>()) {
+        None => settings::STATS_ROWS,
+        Some(Ok(count)) => count,
+        Some(Err(_)) => {
+            eprintln!("{} is not a count", args[1]);
+            return 2;
+        }
+    };
+    let db = db_path();
+    match load(&db) {
+        Ok(plays) => {
+            let top = if top { count } else { 0 };
+            let recent = if recent { count } else { 0 };
+            for line in report(&plays, top, recent) {
+                println!("{}", line);
+            }
+            0
+        }
+        Err(error) => {
+            eprintln!("{}: {}", db.display(), error);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(path: &str, title: &str, time: u64, listened: f64, completed: bool) -> Play {
+        Play {
+            time,
+            path: PathBuf::from(path),
+            artist: "Artist".to_string(),
+            title: title.to_string(),
+            album: String::new(),
+            track: String::new(),
+            duration: 200.0,
+            listened,
+            completed,
+        }
+    }
+
+    fn plays() -> Vec<Play> {
+        vec![
+            play("/m/a.mp3", "A", 100, 200.0, true),
+            play("/m/b.mp3", "B", 200, 30.0, false),
+            play("/m/a.mp3", "A", 300, 50.0, false),
+            play("/m/c.mp3", "C", 400, 200.0, true),
+            play("/m/a.mp3", "A (Remastered)", 500, 200.0, true),
+        ]
+    }
+
+    #[test]
+    fn aggregates_plays_and_skips_per_track() {
+        let tracks = tracks(&plays());
+        let summary: Vec<(&str, usize, usize, f64, u64)> = tracks.iter()
+            .map(|t| (t.name.as_str(), t.plays, t.skips, t.listened, t.last_played))
+            .collect();
+        assert_eq!(summary, vec![
+            ("Artist - A (Remastered)", 2, 1, 450.0, 500),
+            ("Artist - C", 1, 0, 200.0, 400),
+            ("Artist - B", 0, 1, 30.0, 200),
+        ]);
+    }
+
+    #[test]
+    fn reports_top_and_recent_tracks() {
+        let lines = report(&plays(), 5, 2);
+        let lines: Vec<&str> = lines.iter().map(|l| l.trim_start()).collect();
+        assert_eq!(lines[0], "5 plays, 2 skipped, 0 h 11 min listened");
+        let top = lines.iter().position(|l| *l == "Most played").unwrap();
+        assert!(lines[top + 1].starts_with("2×  Artist - A (Remastered)  ("));
+        assert!(lines[top + 1].ends_with(", 1 skipped)"));
+        assert!(lines[top + 2].starts_with("1×  Artist - C  ("));
+        // never completed, so not among the most played
+        assert_eq!(lines[top + 3], "");
+        let recent = top + 4;
+        assert_eq!(lines[recent], "Recently played");
+        assert!(lines[recent + 1].ends_with("d ago  Artist - A (Remastered)"));
+        assert!(lines[recent + 2].ends_with("d ago  Artist - C"));
+        assert_eq!(lines.len(), recent + 3);
+
+        assert_eq!(report(&plays(), 0, 0).len(), 1);
+        assert_eq!(report(&[], 5, 5)[0], "0 plays, 0 skipped, 0 h 00 min listened");
+    }
+
+    #[test]
+    fn survives_a_line_cut_short() {
+        let db = std::env::temp_dir().join(format!("srmp-history-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&db);
+        let plays = plays();
+        record(&db, &plays[0]).unwrap();
+        record(&db, &plays[1]).unwrap();
+        // a crash in the middle of the third line
+        let line = serde_json::to_string(&plays[2]).unwrap();
+        OpenOptions::new().append(true).open(&db).unwrap().write_all(&line.as_bytes()[..line.len() / 2]).unwrap();
+        assert_eq!(load(&db).unwrap().len(), 2);
+
+        record(&db, &plays[3]).unwrap();
+        let loaded: Vec<u64> = load(&db).unwrap().iter().map(|p| p.time).collect();
+        let _ = fs::remove_file(&db);
+        assert_eq!(loaded, vec![100, 200, 400]);
+    }
+}
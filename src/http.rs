@@ -0,0 +1,267 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use crate::command;
+use crate::settings;
+use crate::status::Status;
+use crate::State;
+
+const REMOTE_PAGE: &str = include_str!("../assets/remote.html");
+
+/// Serves the web remote on `address` from a background thread:
+///
+/// - `GET /` the remote page
+/// - `GET /api/status` the player status
+/// - `POST /api/<command>` a command of the control socket, its fields in an optional JSON body
+/// - `GET /api/events` a WebSocket pushing status changes and the position
+///
+/// Web pages open in the user's browser must not drive the player: requests for
+/// another host name (DNS rebinding) or from another origin are refused, and commands
+/// need a JSON content type, which a cross-site form or plain `fetch` cannot send
+/// without the browser asking first.
+///
+/// If the address cannot be bound the player carries on and says so in a notification.
+pub fn spawn(state: Arc<Mutex<State>>, address: String) {
+    thread::spawn(move || {
+        let server = match Server::http(&address) {
+            Ok(server) => server,
+            Err(error) => {
+                state.lock().unwrap().notifications.warning(format!("http: {}: {}", address, error));
+                return;
+            }
+        };
+        let Some(bound) = server.server_addr().to_ip() else {
+            return;
+        };
+        let hosts = Arc::new(Hosts { bound, given: address });
+        for request in server.incoming_requests() {
+            let state = Arc::clone(&state);
+            let hosts = Arc::clone(&hosts);
+            thread::spawn(move || handle(state, &hosts, request));
+        }
+    });
+}
+
+/// The names the server answers to.
+struct Hosts {
+    bound: SocketAddr,
+    /// The address as given on the command line, which may be a host name.
+    given: String,
+}
+
+impl Hosts {
+    /// An IP address with the port is always fine, as DNS rebinding needs a name;
+    /// names are the one given and `localhost` when listening on the loopback.
+    fn allows(&self, host: &str) -> bool {
+        if host == self.given {
+            return true;
+        }
+        if let Ok(address) = host.parse::<SocketAddr>() {
+            return address.port() == self.bound.port();
+        }
+        let Some((name, port)) = host.rsplit_once(':') else {
+            return false;
+        };
+        port.parse() == Ok(self.bound.port()) && name.eq_ignore_ascii_case("localhost") && self.bound.ip().is_loopback()
+    }
+
+    /// The `Origin` of the remote page itself; requests without one do not come from another page.
+    fn allows_origin(&self, origin: &str) -> bool {
+        origin.strip_prefix("http://").is_some_and(|host| self.allows(host))
+    }
+}
+
+fn header_value<'a>(request: &'a Request, field: &'static str) -> Option<&'a str> {
+    request.headers().iter().find(|header| header.field.equiv(field)).map(|header| header.value.as_str())
+}
+
+/// Why a request with the given method and headers is refused, with the status code,
+/// or `None` when it may go on.
+fn refusal<'a>(hosts: &Hosts, method: &Method, header: impl Fn(&'static str) -> Option<&'a str>) -> Option<(u16, &'static str)> {
+    if !header("Host").is_some_and(|host| hosts.allows(host)) {
+        return Some((403, "unknown host"));
+    }
+    if header("Origin").is_some_and(|origin| !hosts.allows_origin(origin)) {
+        return Some((403, "requests from other sites are not allowed"));
+    }
+    let json = header("Content-Type")
+        .is_some_and(|value| value.split(';').next().unwrap_or_default().trim().eq_ignore_ascii_case("application/json"));
+    if *method == Method::Post && !json {
+        return Some((415, "commands need Content-Type: application/json"));
+    }
+    None
+}
+
+fn handle(state: Arc<Mutex<State>>, hosts: &Hosts, mut request: Request) {
+    let path = request.url().split('?').next().unwrap_or_default().to_string();
+    let method = request.method().clone();
+    if let Some((code, error)) = refusal(hosts, &method, |field| header_value(&request, field)) {
+        respond(request, code, json!({ "ok": false, "error": error }));
+        return;
+    }
+    let reply = match (method, path.as_str()) {
+        (Method::Get, "/") => {
+            let page = Response::from_string(REMOTE_PAGE).with_header(content_type("text/html; charset=utf-8"));
+            let _ = request.respond(page);
+            return;
+        }
+        (Method::Get, "/api/status") => (200, json!({ "ok": true, "status": Status::of(&state.lock().unwrap()) })),
+        (Method::Get, "/api/events") => {
+            events(state, request);
+            return;
+        }
+        (Method::Post, path) if path.starts_with("/api/") => {
+            let mut body = String::new();
+            let _ = request.as_reader().read_to_string(&mut body);
+            run(&state, &path["/api/".len()..], &body)
+        }
+        (_, path) => (404, json!({ "ok": false, "error": format!("no such resource {}", path) })),
+    };
+    let (code, value) = reply;
+    respond(request, code, value);
+}
+
+fn respond(request: Request, code: u16, value: Value) {
+    let response = Response::from_string(value.to_string())
+        .with_status_code(code)
+        .with_header(content_type("application/json"));
+    let _ = request.respond(response);
+}
+
+/// The command named by the URL, with the fields of `body` if there is one, goes
+/// through the same `Request` and `Command` as the control socket and the keys.
+fn run(state: &Arc<Mutex<State>>, name: &str, body: &str) -> (u16, Value) {
+    let mut fields = if body.trim().is_empty() {
+        json!({})
+    } else {
+        match serde_json::from_str::<Value>(body) {
+            Ok(fields) if fields.is_object() => fields,
+            _ => return (400, json!({ "ok": false, "error": "the body should be a JSON object" })),
+        }
+    };
+    fields["command"] = json!(name);
+    match serde_json::from_value::<command::Request>(fields) {
+        Ok(command::Request::Status) => (200, json!({ "ok": true, "status": Status::of(&state.lock().unwrap()) })),
+        Ok(command::Request::Subscribe) => (400, json!({ "ok": false, "error": "subscribe through /api/events" })),
+        Ok(request) => {
+            if let Some(command) = request.command() {
+                command.apply(&mut state.lock().unwrap());
+            }
+            (200, json!({ "ok": true }))
+        }
+        Err(error) => (400, json!({ "ok": false, "error": error.to_string() })),
+    }
+}
+
+/// Upgrades to a WebSocket and, every `settings::HTTP_PUSH_TIME` seconds, sends the
+/// events of the control socket and, while playing, `{"event": "position", ...}`.
+/// Incoming messages are not read; the loop ends when the client is gone.
+fn events(state: Arc<Mutex<State>>, request: Request) {
+    let key = header_value(&request, "Sec-WebSocket-Key").map(str::to_string);
+    let Some(key) = key else {
+        let response = Response::from_string("expected a WebSocket upgrade").with_status_code(400);
+        let _ = request.respond(response);
+        return;
+    };
+    let accept = tungstenite::handshake::derive_accept_key(key.as_bytes());
+    let response = Response::empty(101).with_header(header("Sec-WebSocket-Accept", &accept));
+    let stream = request.upgrade("websocket", response);
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+
+    let mut last = Status::of(&state.lock().unwrap());
+    let mut last_time = Instant::now();
+    let mut messages = vec![json!({ "event": "track", "status": last })];
+    loop {
+        for message in messages.drain(..) {
+            if socket.send(Message::Text(message.to_string())).is_err() {
+                return;
+            }
+        }
+        thread::sleep(Duration::from_secs_f64(settings::HTTP_PUSH_TIME));
+        let now = Status::of(&state.lock().unwrap());
+        for event in now.events(&last, last_time.elapsed().as_secs_f64()) {
+            messages.push(json!({ "event": event, "status": now }));
+        }
+        if now.state == "playing" {
+            messages.push(json!({ "event": "position", "position": now.position, "duration": now.duration }));
+        }
+        last = now;
+        last_time = Instant::now();
+    }
+}
+
+fn content_type(value: &str) -> Header {
+    header("Content-Type", value)
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).expect("header should be valid ASCII")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts(bound: &str) -> Hosts {
+        Hosts { bound: bound.parse().unwrap(), given: bound.to_string() }
+    }
+
+    fn check(hosts: &Hosts, method: Method, headers: &[(&'static str, &'static str)]) -> Option<u16> {
+        refusal(hosts, &method, |field| headers.iter().find(|(f, _)| *f == field).map(|(_, v)| *v)).map(|(code, _)| code)
+    }
+
+    const JSON: (&str, &str) = ("Content-Type", "application/json; charset=utf-8");
+
+    #[test]
+    fn allows_the_page_itself() {
+        let local = hosts("127.0.0.1:8080");
+        assert_eq!(check(&local, Method::Get, &[("Host", "127.0.0.1:8080")]), None);
+        assert_eq!(check(&local, Method::Get, &[("Host", "localhost:8080"), ("Origin", "http://localhost:8080")]), None);
+        assert_eq!(check(&local, Method::Post, &[("Host", "LOCALHOST:8080"), ("Origin", "http://127.0.0.1:8080"), JSON]), None);
+    }
+
+    #[test]
+    fn allows_a_lan_address() {
+        let lan = hosts("0.0.0.0:8080");
+        assert_eq!(check(&lan, Method::Post, &[("Host", "192.168.1.20:8080"), ("Origin", "http://192.168.1.20:8080"), JSON]), None);
+        assert_eq!(check(&lan, Method::Get, &[("Host", "[fe80::1]:8080")]), None);
+        // a name resolving to the machine is only known on the loopback
+        assert_eq!(check(&lan, Method::Get, &[("Host", "localhost:8080")]), Some(403));
+    }
+
+    #[test]
+    fn refuses_spoofed_hosts() {
+        let local = hosts("127.0.0.1:8080");
+        assert_eq!(check(&local, Method::Get, &[]), Some(403));
+        assert_eq!(check(&local, Method::Get, &[("Host", "evil.example:8080")]), Some(403));
+        assert_eq!(check(&local, Method::Get, &[("Host", "evil.example")]), Some(403));
+        assert_eq!(check(&local, Method::Get, &[("Host", "127.0.0.1:9090")]), Some(403));
+        assert_eq!(check(&local, Method::Get, &[("Host", "localhost.evil.example:8080")]), Some(403));
+    }
+
+    #[test]
+    fn refuses_other_origins() {
+        let local = hosts("127.0.0.1:8080");
+        for origin in ["http://evil.example", "http://evil.example:8080", "https://127.0.0.1:8080", "null"] {
+            assert_eq!(check(&local, Method::Post, &[("Host", "127.0.0.1:8080"), ("Origin", origin), JSON]), Some(403), "{}", origin);
+        }
+        // no Origin at all is a request from a tool, not from a page
+        assert_eq!(check(&local, Method::Post, &[("Host", "127.0.0.1:8080"), JSON]), None);
+    }
+
+    #[test]
+    fn refuses_commands_without_json() {
+        let local = hosts("127.0.0.1:8080");
+        for content_type in ["text/plain", "application/x-www-form-urlencoded", "multipart/form-data; boundary=x"] {
+            assert_eq!(check(&local, Method::Post, &[("Host", "127.0.0.1:8080"), ("Content-Type", content_type)]), Some(415));
+        }
+        assert_eq!(check(&local, Method::Post, &[("Host", "127.0.0.1:8080")]), Some(415));
+        assert_eq!(check(&local, Method::Get, &[("Host", "127.0.0.1:8080")]), None);
+    }
+}
@@ -2,6 +2,7 @@ use miniquad::*;
 use std::thread::sleep;
 use std::time::Duration;
 
+use crate::keymap::{self, Action, Combo, Keymap};
 use crate::settings;
 
 pub struct TimeState {
@@ -19,11 +20,12 @@ impl TimeState {
         }
     }
 
-    pub fn frame_time(&mut self) {
+    /// Sleeps out the rest of a frame of `desired` seconds.
+    pub fn frame_time(&mut self, desired: f64) {
         self.frame_time = date::now() - self.last_frame;
-        if self.frame_time < settings::FT_DESIRED {
+        if self.frame_time < desired {
             sleep(Duration::from_secs_f64(
-                settings::FT_DESIRED - self.frame_time,
+                desired - self.frame_time,
             ));
         }
         self.frame_time = date::now() - self.last_frame;
@@ -32,82 +34,97 @@ impl TimeState {
     }
 }
 
-pub struct KeysState {
-    pub w: bool,
-    pub a: bool,
-    pub s: bool,
-    pub d: bool,
-    pub q: bool,
-    pub e: bool,
-    pub k: bool,
-    pub l: bool,
-    pub f: bool,
-    pub m: bool,
-    pub r: bool,
-    pub esc: bool,
-    pub left: bool,
-    pub right: bool,
-    pub up: bool,
-    pub down: bool,
-    pub space: bool,
-    pub enter: bool,
+/// Turns key presses into actions through the keymap, holding the start of a chord
+/// until the next key completes it or `settings::CHORD_TIMEOUT` runs out.
+pub struct InputState {
+    pub keymap: Keymap,
+    pub actions: Vec<Action>,
+    pending: Vec<Combo>,
+    pending_time: f64,
 }
 
-impl KeysState {
-    pub fn read_key(&mut self, keycode: KeyCode, state: bool) {
-        match keycode {
-            KeyCode::W => self.w = state,
-            KeyCode::S => self.s = state,
-            KeyCode::A => self.a = state,
-            KeyCode::D => self.d = state,
-            KeyCode::Left => self.left = state,
-            KeyCode::Right => self.right = state,
-            KeyCode::Up => self.up = state,
-            KeyCode::Down => self.down = state,
-            KeyCode::Space => self.space = state,
-            KeyCode::Escape => self.esc = state,
-            KeyCode::Enter => self.enter = state,
-            KeyCode::K => self.k = state,
-            KeyCode::R => self.r = state,
-            KeyCode::L => self.l = state,
-            KeyCode::Q => self.q = state,
-            KeyCode::E => self.e = state,
-            KeyCode::F => self.f = state,
-            KeyCode::M => self.m = state,
-            _ => {},
+impl InputState {
+    pub fn init(keymap: Keymap) -> InputState {
+        InputState {
+            keymap,
+            actions: Vec::new(),
+            pending: Vec::new(),
+            pending_time: 0.0,
         }
     }
-}
 
-pub struct InputState {
-    pub keys: KeysState,
-    pub apply_change: bool,
-}
+    /// While there is a search `query` to type into, character keys without Ctrl, Alt or
+    /// Super are left to the text input; Space only once the query has begun, so it still
+    /// plays and pauses before.
+    /// A key held down (`repeat`) only repeats actions bound to it alone that are meant to repeat.
+    pub fn key_down(&mut self, keycode: KeyCode, keymods: KeyMods, repeat: bool, query: Option<&str>) {
+        if Combo::is_modifier(keycode) {
+            return;
+        }
+        let typing = query.is_some_and(|query| keycode != KeyCode::Space || !query.is_empty());
+        let combo = Combo::new(keycode, keymods);
+        if repeat {
+            if typing && combo.is_text() {
+                return;
+            }
+            if let Some(action) = self.keymap.find(&[combo]).filter(|action| action.repeats()) {
+                self.actions.push(action);
+            }
+            return;
+        }
+        let now = date::now();
+        if now - self.pending_time > settings::CHORD_TIMEOUT {
+            self.pending.clear();
+        }
+        if self.pending.is_empty() && typing && combo.is_text() {
+            return;
+        }
 
-impl InputState {
-    pub fn init() -> InputState {
-        InputState {
-            keys: KeysState {
-                w: false,
-                a: false,
-                s: false,
-                d: false,
-                q: false,
-                e: false,
-                k: false,
-                l: false,
-                f: false,
-                m: false,
-                r: false,
-                left: false,
-                right: false,
-                up: false,
-                down: false,
-                space: false,
-                enter: false,
-                esc: false,
-            },
-            apply_change: false,
+        let mut sequence = self.pending.clone();
+        sequence.push(combo);
+        if !self.pending.is_empty() && self.keymap.find(&sequence).is_none() && !self.keymap.is_prefix(&sequence) {
+            // not a chord after all, start over from this key
+            sequence = vec![combo];
+            if typing && combo.is_text() {
+                self.pending.clear();
+                return;
+            }
+        }
+        self.pending.clear();
+        if let Some(action) = self.keymap.find(&sequence) {
+            self.actions.push(action);
+        } else if self.keymap.is_prefix(&sequence) {
+            self.pending = sequence;
+            self.pending_time = now;
+        }
+    }
+
+    /// The chord typed so far, for the overlay.
+    pub fn pending(&self) -> Option<String> {
+        if self.pending.is_empty() || date::now() - self.pending_time > settings::CHORD_TIMEOUT {
+            None
+        } else {
+            Some(keymap::sequence_string(&self.pending))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actions(keycode: KeyCode, query: Option<&str>) -> Vec<Action> {
+        let mut input = InputState::init(Keymap::defaults());
+        input.key_down(keycode, KeyMods::default(), false, query);
+        input.actions
+    }
+
+    #[test]
+    fn space_types_only_into_a_begun_query() {
+        assert_eq!(actions(KeyCode::Space, None), vec![Action::PlayPause]);
+        assert_eq!(actions(KeyCode::Space, Some("")), vec![Action::PlayPause]);
+        assert_eq!(actions(KeyCode::Space, Some("moon")), vec![]);
+        assert_eq!(actions(KeyCode::S, None), vec![Action::Next]);
+        assert_eq!(actions(KeyCode::S, Some("")), vec![]);
+    }
+}
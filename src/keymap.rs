@@ -0,0 +1,329 @@
+use miniquad::{KeyCode, KeyMods};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::settings;
+
+/// Everything the keyboard can do; the stage decides what each action means in the current view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    PlayPause,
+    Next,
+    Previous,
+    Stop,
+    SeekForward,
+    SeekBackward,
+    VolumeUp,
+    VolumeDown,
+    ToggleTime,
+    PlayMode,
+    NextTheme,
+    NextVisualizer,
+    Playlist,
+    Fullscreen,
+    Presentation,
+    FocusNext,
+    Decrease,
+    Increase,
+    SelectUp,
+    SelectDown,
+    PlaySelected,
+    Erase,
+    Help,
+    Stats,
+    Back,
+    Quit,
+}
+
+impl Action {
+    /// Whether holding its key down keeps repeating it; toggles and view changes fire once.
+    pub fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::SeekForward | Action::SeekBackward | Action::VolumeUp | Action::VolumeDown
+                | Action::Decrease | Action::Increase | Action::SelectUp | Action::SelectDown | Action::Erase
+        )
+    }
+}
+
+/// Name in the bindings file, description and default bindings of every action.
+pub const ACTIONS: &[(Action, &str, &str, &[&str])] = &[
+    (Action::PlayPause, "play_pause", "play / pause", &["Space"]),
+    (Action::Next, "next", "next track", &["S"]),
+    (Action::Previous, "previous", "previous track", &[]),
+    (Action::Stop, "stop", "stop", &[]),
+    (Action::SeekForward, "seek_forward", "seek forward", &["Shift+Right"]),
+    (Action::SeekBackward, "seek_backward", "seek backward", &["Shift+Left"]),
+    (Action::VolumeUp, "volume_up", "volume up", &["Shift+Up"]),
+    (Action::VolumeDown, "volume_down", "volume down", &["Shift+Down"]),
+    (Action::ToggleTime, "toggle_time", "elapsed / remaining time", &["T"]),
+    (Action::PlayMode, "play_mode", "repeat / repeat one / once", &["M"]),
+    (Action::NextTheme, "next_theme", "next theme", &["C"]),
+    (Action::NextVisualizer, "next_visualizer", "next visualizer", &["V"]),
+    (Action::Playlist, "playlist", "open the playlist", &["P"]),
+    (Action::Fullscreen, "fullscreen", "fullscreen on / off", &["F", "F11"]),
+    (Action::Presentation, "presentation", "visualizer only on / off", &["Shift+F"]),
+    (Action::FocusNext, "focus_next", "focus the next slider", &["Tab"]),
+    (Action::Decrease, "decrease", "move the focused slider left", &["Left"]),
+    (Action::Increase, "increase", "move the focused slider right", &["Right"]),
+    (Action::SelectUp, "select_up", "playlist selection up", &["Up"]),
+    (Action::SelectDown, "select_down", "playlist selection down", &["Down"]),
+    (Action::PlaySelected, "play_selected", "play the selected track", &["Enter"]),
+    (Action::Erase, "erase", "delete the last search character", &["Backspace"]),
+    (Action::Help, "help", "show / hide this help", &["H", "F1"]),
+    (Action::Stats, "stats", "show / hide the listening statistics", &["I"]),
+    (Action::Back, "back", "leave a view, clear search, close playlist", &["Escape"]),
+    (Action::Quit, "quit", "quit", &["Ctrl+Q"]),
+];
+
+const KEYS: &[(&str, KeyCode)] = &[
+    ("Space", KeyCode::Space), ("Apostrophe", KeyCode::Apostrophe), ("Comma", KeyCode::Comma),
+    ("Minus", KeyCode::Minus), ("Period", KeyCode::Period), ("Slash", KeyCode::Slash),
+    ("0", KeyCode::Key0), ("1", KeyCode::Key1), ("2", KeyCode::Key2), ("3", KeyCode::Key3),
+    ("4", KeyCode::Key4), ("5", KeyCode::Key5), ("6", KeyCode::Key6), ("7", KeyCode::Key7),
+    ("8", KeyCode::Key8), ("9", KeyCode::Key9), ("Semicolon", KeyCode::Semicolon), ("Equal", KeyCode::Equal),
+    ("A", KeyCode::A), ("B", KeyCode::B), ("C", KeyCode::C), ("D", KeyCode::D), ("E", KeyCode::E),
+    ("F", KeyCode::F), ("G", KeyCode::G), ("H", KeyCode::H), ("I", KeyCode::I), ("J", KeyCode::J),
+    ("K", KeyCode::K), ("L", KeyCode::L), ("M", KeyCode::M), ("N", KeyCode::N), ("O", KeyCode::O),
+    ("P", KeyCode::P), ("Q", KeyCode::Q), ("R", KeyCode::R), ("S", KeyCode::S), ("T", KeyCode::T),
+    ("U", KeyCode::U), ("V", KeyCode::V), ("W", KeyCode::W), ("X", KeyCode::X), ("Y", KeyCode::Y),
+    ("Z", KeyCode::Z), ("LeftBracket", KeyCode::LeftBracket), ("Backslash", KeyCode::Backslash),
+    ("RightBracket", KeyCode::RightBracket), ("Grave", KeyCode::GraveAccent),
+    ("Escape", KeyCode::Escape), ("Enter", KeyCode::Enter), ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace), ("Insert", KeyCode::Insert), ("Delete", KeyCode::Delete),
+    ("Right", KeyCode::Right), ("Left", KeyCode::Left), ("Down", KeyCode::Down), ("Up", KeyCode::Up),
+    ("PageUp", KeyCode::PageUp), ("PageDown", KeyCode::PageDown), ("Home", KeyCode::Home), ("End", KeyCode::End),
+    ("Pause", KeyCode::Pause), ("F1", KeyCode::F1), ("F2", KeyCode::F2), ("F3", KeyCode::F3),
+    ("F4", KeyCode::F4), ("F5", KeyCode::F5), ("F6", KeyCode::F6), ("F7", KeyCode::F7), ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9), ("F10", KeyCode::F10), ("F11", KeyCode::F11), ("F12", KeyCode::F12),
+    ("Kp0", KeyCode::Kp0), ("Kp1", KeyCode::Kp1), ("Kp2", KeyCode::Kp2), ("Kp3", KeyCode::Kp3),
+    ("Kp4", KeyCode::Kp4), ("Kp5", KeyCode::Kp5), ("Kp6", KeyCode::Kp6), ("Kp7", KeyCode::Kp7),
+    ("Kp8", KeyCode::Kp8), ("Kp9", KeyCode::Kp9), ("KpDecimal", KeyCode::KpDecimal),
+    ("KpDivide", KeyCode::KpDivide), ("KpMultiply", KeyCode::KpMultiply), ("KpSubtract", KeyCode::KpSubtract),
+    ("KpAdd", KeyCode::KpAdd), ("KpEnter", KeyCode::KpEnter), ("Menu", KeyCode::Menu),
+];
+
+/// One key press with the modifiers held down, e.g. `Ctrl+Shift+N`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Combo {
+    pub key: KeyCode,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+impl Combo {
+    pub fn new(key: KeyCode, mods: KeyMods) -> Combo {
+        Combo {
+            key,
+            ctrl: mods.ctrl,
+            shift: mods.shift,
+            alt: mods.alt,
+            logo: mods.logo,
+        }
+    }
+
+    /// Parses `Ctrl+Alt+K`; modifier and key names ignore case.
+    pub fn parse(text: &str) -> Result<Combo, String> {
+        let mut parts: Vec<&str> = text.split('+').map(|p| p.trim()).collect();
+        let key_name = parts.pop().unwrap_or_default();
+        let key = KEYS.iter().find(|(name, _)| name.eq_ignore_ascii_case(key_name)).map(|&(_, key)| key);
+        let mut combo = Combo::new(key.ok_or_else(|| format!("unknown key \"{}\"", key_name))?, KeyMods::default());
+        for part in parts {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => combo.ctrl = true,
+                "shift" => combo.shift = true,
+                "alt" => combo.alt = true,
+                "super" | "logo" | "cmd" => combo.logo = true,
+                _ => return Err(format!("unknown modifier \"{}\"", part)),
+            }
+        }
+        Ok(combo)
+    }
+
+    /// A key without Ctrl, Alt or Super that produces a character, which goes to the search box while typing.
+    pub fn is_text(&self) -> bool {
+        !self.ctrl && !self.alt && !self.logo && (self.key as u32) < 0x100
+    }
+
+    pub fn is_modifier(key: KeyCode) -> bool {
+        matches!(
+            key,
+            KeyCode::LeftShift | KeyCode::RightShift | KeyCode::LeftControl | KeyCode::RightControl
+                | KeyCode::LeftAlt | KeyCode::RightAlt | KeyCode::LeftSuper | KeyCode::RightSuper
+        )
+    }
+}
+
+impl std::fmt::Display for Combo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (held, name) in [(self.ctrl, "Ctrl+"), (self.alt, "Alt+"), (self.shift, "Shift+"), (self.logo, "Super+")] {
+            if held {
+                write!(f, "{}", name)?;
+            }
+        }
+        let name = KEYS.iter().find(|&&(_, key)| key == self.key).map_or("?", |&(name, _)| name);
+        write!(f, "{}", name)
+    }
+}
+
+/// A key binding: a single combo, or a chord of combos pressed one after the other.
+pub type Sequence = Vec<Combo>;
+
+pub fn sequence_string(sequence: &[Combo]) -> String {
+    sequence.iter().map(|c| c.to_string()).collect::<Vec<String>>().join(" ")
+}
+
+/// Maps key sequences to actions. Actions the bindings file leaves out keep their defaults.
+pub struct Keymap {
+    pub bindings: Vec<(Sequence, Action)>,
+}
+
+impl Keymap {
+    pub fn defaults() -> Keymap {
+        let mut bindings = Vec::new();
+        for &(action, _, _, keys) in ACTIONS {
+            for key in keys {
+                bindings.push((vec![Combo::parse(key).expect("bad default binding")], action));
+            }
+        }
+        Keymap { bindings }
+    }
+
+    /// Reads `bindings.toml` from the config directory. A missing file gives the defaults;
+    /// a file that does not parse or binds one sequence twice is rejected as a whole.
+    pub fn load() -> (Keymap, Option<String>) {
+        let path = bindings_path();
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return (Keymap::defaults(), None),
+        };
+        match Keymap::parse(&text) {
+            Ok(keymap) => (keymap, None),
+            Err(error) => (Keymap::defaults(), Some(format!("bindings.toml: {}", error))),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Keymap, String> {
+        let table: HashMap<String, Vec<String>> = toml::from_str(text).map_err(|e| e.message().to_string())?;
+        let mut keymap = Keymap::defaults();
+        for (name, keys) in table {
+            let action = match ACTIONS.iter().find(|(_, n, _, _)| *n == name) {
+                Some(&(action, _, _, _)) => action,
+                None => return Err(format!("unknown action \"{}\"", name)),
+            };
+            keymap.bindings.retain(|(_, a)| *a != action);
+            for key in keys {
+                let sequence = key.split_whitespace().map(Combo::parse).collect::<Result<Sequence, String>>()?;
+                if sequence.is_empty() {
+                    return Err(format!("{}: empty binding", name));
+                }
+                keymap.bindings.push((sequence, action));
+            }
+        }
+        keymap.check()?;
+        Ok(keymap)
+    }
+
+    /// A sequence bound to two actions is ambiguous, and so is one that starts another:
+    /// the chord could never be completed.
+    fn check(&self) -> Result<(), String> {
+        let mut conflicts = Vec::new();
+        for (i, (a, action_a)) in self.bindings.iter().enumerate() {
+            for (b, action_b) in self.bindings.iter().skip(i + 1) {
+                let name_a = name(*action_a);
+                let name_b = name(*action_b);
+                if a == b && action_a != action_b {
+                    conflicts.push(format!("{} is bound to {} and {}", sequence_string(a), name_a, name_b));
+                } else if a.len() < b.len() && b.starts_with(a) {
+                    conflicts.push(format!("{} ({}) blocks {} ({})", sequence_string(a), name_a, sequence_string(b), name_b));
+                } else if b.len() < a.len() && a.starts_with(b) {
+                    conflicts.push(format!("{} ({}) blocks {} ({})", sequence_string(b), name_b, sequence_string(a), name_a));
+                }
+            }
+        }
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(conflicts.join("; "))
+        }
+    }
+
+    pub fn find(&self, sequence: &[Combo]) -> Option<Action> {
+        self.bindings.iter().find(|(s, _)| s == sequence).map(|&(_, action)| action)
+    }
+
+    /// True when `sequence` is the start of a longer chord.
+    pub fn is_prefix(&self, sequence: &[Combo]) -> bool {
+        self.bindings.iter().any(|(s, _)| s.len() > sequence.len() && s.starts_with(sequence))
+    }
+
+    /// The key sequences bound to `action`, in the order they were defined.
+    pub fn keys(&self, action: Action) -> Vec<String> {
+        self.bindings.iter().filter(|(_, a)| *a == action).map(|(s, _)| sequence_string(s)).collect()
+    }
+}
+
+pub fn name(action: Action) -> &'static str {
+    ACTIONS.iter().find(|(a, _, _, _)| *a == action).map_or("?", |&(_, name, _, _)| name)
+}
+
+pub fn bindings_path() -> PathBuf {
+    settings::config_dir().join("bindings.toml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combo(key: KeyCode, ctrl: bool, shift: bool) -> Combo {
+        Combo { key, ctrl, shift, alt: false, logo: false }
+    }
+
+    #[test]
+    fn defaults_pass_the_check() {
+        Keymap::defaults().check().unwrap();
+        for (i, &(action, name, _, _)) in ACTIONS.iter().enumerate() {
+            assert!(ACTIONS[i + 1..].iter().all(|&(a, n, _, _)| a != action && n != name), "{} is listed twice", name);
+        }
+    }
+
+    #[test]
+    fn parses_combos_and_chords() {
+        assert_eq!(Combo::parse("ctrl+shift+n"), Ok(combo(KeyCode::N, true, true)));
+        assert_eq!(Combo::parse("Control + Left"), Ok(combo(KeyCode::Left, true, false)));
+        assert_eq!(Combo::parse("Ctrl+Hyper+N"), Err("unknown modifier \"Hyper\"".to_string()));
+        assert_eq!(Combo::parse("Ctrl+Knob"), Err("unknown key \"Knob\"".to_string()));
+        assert_eq!(Combo::parse("Super+Alt+Shift+Ctrl+F5").unwrap().to_string(), "Ctrl+Alt+Shift+Super+F5");
+
+        let keymap = Keymap::parse("next = [\"Ctrl+K N\"]\nprevious = [\"Ctrl+K B\"]").unwrap();
+        let ctrl_k = combo(KeyCode::K, true, false);
+        assert_eq!(keymap.find(&[ctrl_k, combo(KeyCode::N, false, false)]), Some(Action::Next));
+        assert!(keymap.is_prefix(&[ctrl_k]));
+        assert_eq!(keymap.find(&[ctrl_k]), None);
+        // listing an action replaces its defaults
+        assert_eq!(keymap.find(&[combo(KeyCode::S, false, false)]), None);
+        assert_eq!(keymap.keys(Action::Next), vec!["Ctrl+K N"]);
+        assert_eq!(keymap.keys(Action::PlayPause), vec!["Space"]);
+    }
+
+    #[test]
+    fn rejects_conflicts_and_blocked_chords() {
+        let error = Keymap::parse("next = [\"Space\"]").err().unwrap();
+        assert!(error.contains("Space is bound to "), "{}", error);
+        let error = Keymap::parse("next = [\"Ctrl+K\"]\nprevious = [\"Ctrl+K B\"]").err().unwrap();
+        assert_eq!(error, "Ctrl+K (next) blocks Ctrl+K B (previous)");
+        // the same keys twice for one action are harmless
+        assert!(Keymap::parse("next = [\"N\", \"N\"]").is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert_eq!(Keymap::parse("rewind = [\"R\"]").err(), Some("unknown action \"rewind\"".to_string()));
+        assert_eq!(Keymap::parse("next = [\" \"]").err(), Some("next: empty binding".to_string()));
+        assert!(Keymap::parse("next = \"N\"").is_err());
+        assert!(Keymap::parse("next = [\"Ctrl+K Nope\"]").is_err());
+    }
+}
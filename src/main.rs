@@ -1,24 +1,57 @@
 #![windows_subsystem = "windows"]
 
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use miniquad::{self, conf::Platform, conf::Conf};
 
 mod audio;
+mod analysis;
+mod beat;
+mod command;
+mod config;
+#[cfg(unix)]
+mod control;
 mod settings;
 mod assets;
+mod font;
+mod history;
+mod http;
 mod input;
+mod keymap;
 mod mesh;
+#[cfg(target_os = "linux")]
+mod mpris;
+#[cfg(target_os = "linux")]
+mod notify;
+mod notifications;
+mod now_playing;
 mod text;
 mod shaders;
+mod spectrum;
+mod scrobbler;
+mod search;
+mod seekable;
+mod session;
 mod stage;
+mod status;
+mod tags;
+#[cfg(all(test, target_os = "linux"))]
+mod test_bus;
+#[cfg(test)]
+mod test_wav;
+mod theme;
+mod widgets;
 
-fn window_conf() -> Conf {
+fn window_conf(display: &config::Display) -> Conf {
     let mut conf = Conf {
         window_title: "Player".to_owned(),
-        window_width: settings::WIDTH0,
-        window_height: settings::HEIGHT0,
+        window_width: display.width,
+        window_height: display.height,
+        fullscreen: display.fullscreen,
         window_resizable: true,
+        high_dpi: true,
         platform: Platform::default(),
         ..Default::default()
     };
@@ -30,33 +63,132 @@ fn window_conf() -> Conf {
 #[derive(Debug, Clone)]
 pub struct State {
     pub play: bool,
+    /// Stopped rather than paused: back at the start of the track until played again.
+    pub stopped: bool,
     pub skip: bool,
-    pub repeat: bool,
+    pub previous: bool,
+    pub play_mode: config::PlayMode,
     pub dir_name: String,
     pub file_num: usize,
     pub file_name: String,
     pub file_ext: String,
-    pub message: String,
+    pub file_path: PathBuf,
+    pub playlist: Vec<PathBuf>,
+    pub track_index: usize,
+    pub play_index: Option<usize>,
+    pub queue: Vec<PathBuf>,
+    pub tags: HashMap<PathBuf, tags::Tags>,
+    pub position: f64,
+    pub duration: f64,
+    pub seek: Option<f64>,
+    pub volume: f32,
+    pub waveform: Option<analysis::Waveform>,
+    pub beats: u64,
+    pub pulse: f32,
+    pub tempo: f32,
+    pub notifications: notifications::Notifications,
     pub sample_stats: [i16; settings::SAMPLES],
+    /// The configuration file as last loaded; the stage reloads it when it changes.
+    pub config: config::Config,
+}
+
+impl State {
+    /// Nothing playing yet, the play mode from `config`.
+    pub fn new(config: config::Config) -> State {
+        State {
+            play: true,
+            stopped: false,
+            skip: false,
+            previous: false,
+            play_mode: config.playback.play_mode,
+            file_num: 0,
+            dir_name: format!("Directory not found"),
+            file_name: format!("File not found"),
+            file_ext: format!("Unknown"),
+            file_path: PathBuf::new(),
+            playlist: Vec::new(),
+            track_index: 0,
+            play_index: None,
+            queue: Vec::new(),
+            tags: HashMap::new(),
+            position: 0.0,
+            duration: 0.0,
+            seek: None,
+            volume: 1.0,
+            waveform: None,
+            beats: 0,
+            pulse: 0.0,
+            tempo: 0.0,
+            notifications: notifications::Notifications::default(),
+            sample_stats: [0; settings::SAMPLES],
+            config,
+        }
+    }
 }
 
 fn main() {
-    let state = State {
-        play: true,
-        skip: false,
-        repeat: false,
-        file_num: 0,
-        dir_name: format!("Directory not found"),
-        file_name: format!("File not found"),
-        file_ext: format!("Unknown"),
-        message: format!("***"),
-        sample_stats: [0; settings::SAMPLES],
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("ctl") {
+        #[cfg(unix)]
+        std::process::exit(control::client(&args[1..]));
+        #[cfg(not(unix))]
+        {
+            eprintln!("ctl needs a Unix domain socket, which this platform lacks");
+            std::process::exit(1);
+        }
+    }
+    if args.first().map(String::as_str) == Some("stats") {
+        std::process::exit(history::cli(&args[1..]));
+    }
+    // the web remote and the now playing files only when asked for
+    let http_address = option(&args, "--http", settings::HTTP_ADDRESS);
+    let now_playing_dir = option(&args, "--now-playing", &settings::runtime_dir().to_string_lossy());
+    let now_playing_template = option(&args, "--now-playing-template", settings::NOW_PLAYING_TEMPLATE);
+
+    let fresh = args.iter().any(|arg| arg == "--fresh");
+    let (config, config_error) = config::Config::load();
+
+    let mut state = State::new(config.clone());
+    if let Some(error) = config_error {
+        state.notifications.error(format!("config: {}", error));
+    }
+    // pick up where the last run left off, unless asked to start over
+    let resume = match session::Session::load(&session::path()) {
+        Ok(Some(saved)) if !fresh => saved.restore(&mut state),
+        Ok(_) => None,
+        Err(error) => {
+            state.notifications.warning(format!("session: {}", error));
+            None
+        }
     };
 
     let state = Arc::new(Mutex::new(state));
     let state_player = Arc::clone(&state);
 
-    thread::spawn(|| audio::playback(state_player));
+    thread::spawn(|| audio::playback(state_player, resume));
+    session::spawn(Arc::clone(&state));
+    #[cfg(target_os = "linux")]
+    mpris::spawn(Arc::clone(&state));
+    #[cfg(target_os = "linux")]
+    notify::spawn(Arc::clone(&state));
+    #[cfg(unix)]
+    control::spawn(Arc::clone(&state));
+    if let Some(address) = http_address {
+        http::spawn(Arc::clone(&state), address);
+    }
+    if let Some(dir) = now_playing_dir {
+        let template = now_playing_template.unwrap_or_else(|| settings::NOW_PLAYING_TEMPLATE.to_string());
+        now_playing::spawn(Arc::clone(&state), PathBuf::from(dir), template);
+    }
+
+    miniquad::start(window_conf(&config.display), move || {Box::new(stage::Stage::new(state))});
+}
 
-    miniquad::start(window_conf(), move || {Box::new(stage::Stage::new(state))});
+/// The value of `--name=VALUE` among `args`, or `default` for a bare `--name`.
+fn option(args: &[String], name: &str, default: &str) -> Option<String> {
+    args.iter().find_map(|arg| match arg.strip_prefix(name) {
+        Some("") => Some(default.to_string()),
+        Some(value) => value.strip_prefix('=').map(str::to_string),
+        None => None,
+    })
 }
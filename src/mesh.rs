@@ -1,4 +1,7 @@
+use crate::analysis;
+use crate::font::{Fonts, Glyph};
 use crate::text;
+use crate::widgets;
 use crate::settings;
 
 #[repr(C)]
@@ -52,70 +55,23 @@ pub struct Mesh {
 }
 
 impl Mesh {
-    pub fn new_overlay(overlay: &text::Overlay, scalex: f32, scaley: f32) -> Mesh {
+    pub fn new_overlay(overlay: &text::Overlay, fonts: &mut Fonts, scalex: f32, scaley: f32) -> Mesh {
         let mut vertices: Vec<Vertex> = Vec::new();
         let mut indices: Vec<i16> = Vec::new();
         let mut idx = 0;
 
-        let mut tex_uv;
+        let ascent = fonts.ascent(overlay.scale);
 
         for s in 0..overlay.lines.len() {
-            let coords = text::string_to_uv(&overlay.lines[s]);
-
-            for l in 0..coords.len() {
-                tex_uv = coords[l];
-
-                let lf = l as f32;
-
-                let x = (overlay.line_x[s] + (lf + 1.0) * text::WIDTH * overlay.scale) * scalex;
-                let y = overlay.line_y[s] * scaley;
-                vertices.push(Vertex {
-                    pos: Vec3 { x, y, z: 0.0 },
-                    uv: Vec2 {
-                        x: tex_uv.u2,
-                        y: tex_uv.v1,
-                    },
-                    act: 0.0,
-                }); // top right
-                let x = (overlay.line_x[s] + (lf + 1.0) * text::WIDTH * overlay.scale) * scalex;
-                let y = (overlay.line_y[s] + overlay.line_height) * scaley;
-                vertices.push(Vertex {
-                    pos: Vec3 { x, y, z: 0.0 },
-                    uv: Vec2 {
-                        x: tex_uv.u2,
-                        y: tex_uv.v2,
-                    },
-                    act: 0.0,
-                }); // bottom right
-                let x = (overlay.line_x[s] + lf * text::WIDTH * overlay.scale) * scalex;
-                let y = (overlay.line_y[s] + overlay.line_height) * scaley;
-                vertices.push(Vertex {
-                    pos: Vec3 { x, y, z: 0.0 },
-                    uv: Vec2 {
-                        x: tex_uv.u1,
-                        y: tex_uv.v2,
-                    },
-                    act: 0.0,
-                }); // bottom left
-                let x = (overlay.line_x[s] + lf * text::WIDTH * overlay.scale) * scalex;
-                let y = (overlay.line_y[s]) * scaley;
-                vertices.push(Vertex {
-                    pos: Vec3 { x, y, z: 0.0 },
-                    uv: Vec2 {
-                        x: tex_uv.u1,
-                        y: tex_uv.v1,
-                    },
-                    act: 0.0,
-                }); // top left
-
-                indices.push(4 * idx + 0);
-                indices.push(4 * idx + 1);
-                indices.push(4 * idx + 2);
-                indices.push(4 * idx + 2);
-                indices.push(4 * idx + 3);
-                indices.push(4 * idx + 0);
-
-                idx = idx + 1;
+            for (pen, glyph) in fonts.layout(&overlay.lines[s], overlay.scale) {
+                if glyph.width == 0.0 {
+                    continue;
+                }
+                let x = overlay.line_x[s] - overlay.line_scroll[s] + pen;
+                if let Some((rect, uv)) = glyph_rect(&glyph, x, overlay.line_y[s] + ascent, overlay.clip(s), scalex, scaley) {
+                    // act carries the opacity of the line, for fading toasts
+                    push_quad(&mut vertices, &mut indices, &mut idx, rect, uv, overlay.line_alpha[s]);
+                }
             }
         }
 
@@ -126,12 +82,13 @@ impl Mesh {
         }
     }
 
-    pub fn new_gui(gui: &text::GUI, scalex: f32, scaley: f32) -> Mesh {
+    pub fn new_gui(gui: &text::GUI, widgets: &widgets::Widgets, fonts: &mut Fonts, scalex: f32, scaley: f32) -> Mesh {
         let mut vertices: Vec<Vertex> = Vec::new();
         let mut indices: Vec<i16> = Vec::new();
         let mut idx = 0;
 
-        let mut tex_uv = text::string_to_uv("=")[0];
+        // act < 0 marks the panel quad for the GUI shader
+        let tex_uv = fonts.panel_uv();
 
         let x = 0.5 * (1.0 + 1.2 * gui.max_width * scalex);
         let y = (gui.line_y[0] - 1.0 * gui.line_height) * scaley;
@@ -141,7 +98,7 @@ impl Mesh {
                 x: tex_uv.u2,
                 y: tex_uv.v1,
             },
-            act: 0.0,
+            act: -1.0,
         }); // top right
         let x = 0.5 * (1.0 + 1.2 * gui.max_width * scalex);
         let y = (gui.line_y[gui.lines.len() - 1] + 2.0 * gui.line_height) * scaley;
@@ -151,7 +108,7 @@ impl Mesh {
                 x: tex_uv.u2,
                 y: tex_uv.v2,
             },
-            act: 0.0,
+            act: -1.0,
         }); // bottom right
         let x = 0.5 * (1.0 - 1.2 * gui.max_width * scalex);
         let y = (gui.line_y[gui.lines.len() - 1] + 2.0 * gui.line_height) * scaley;
@@ -161,7 +118,7 @@ impl Mesh {
                 x: tex_uv.u1,
                 y: tex_uv.v2,
             },
-            act: 0.0,
+            act: -1.0,
         }); // bottom left
         let x = 0.5 * (1.0 - 1.2 * gui.max_width * scalex);
         let y = (gui.line_y[0] - 1.0 * gui.line_height) * scaley;
@@ -171,7 +128,7 @@ impl Mesh {
                 x: tex_uv.u1,
                 y: tex_uv.v1,
             },
-            act: 0.0,
+            act: -1.0,
         }); // top left
 
         indices.push(4 * idx + 0);
@@ -183,66 +140,111 @@ impl Mesh {
 
         idx = idx + 1;
 
+        let ascent = fonts.ascent(gui.scale);
+
         for s in 0..gui.lines.len() {
-            let coords = text::string_to_uv(&gui.lines[s]);
-
-            for l in 0..coords.len() {
-                tex_uv = coords[l];
-
-                let lf = l as f32;
-
-                let x = (gui.line_x[s] + (lf + 1.0) * text::WIDTH * gui.scale) * scalex;
-                let y = gui.line_y[s] * scaley;
-                vertices.push(Vertex {
-                    pos: Vec3 { x, y, z: 0.0 },
-                    uv: Vec2 {
-                        x: tex_uv.u2,
-                        y: tex_uv.v1,
-                    },
-                    act: gui.line_active[s] as f32,
-                }); // top right
-                let x = (gui.line_x[s] + (lf + 1.0) * text::WIDTH * gui.scale) * scalex;
-                let y = (gui.line_y[s] + gui.line_height) * scaley;
-                vertices.push(Vertex {
-                    pos: Vec3 { x, y, z: 0.0 },
-                    uv: Vec2 {
-                        x: tex_uv.u2,
-                        y: tex_uv.v2,
-                    },
-                    act: gui.line_active[s] as f32,
-                }); // bottom right
-                let x = (gui.line_x[s] + lf * text::WIDTH * gui.scale) * scalex;
-                let y = (gui.line_y[s] + gui.line_height) * scaley;
-                vertices.push(Vertex {
-                    pos: Vec3 { x, y, z: 0.0 },
-                    uv: Vec2 {
-                        x: tex_uv.u1,
-                        y: tex_uv.v2,
-                    },
-                    act: gui.line_active[s] as f32,
-                }); // bottom left
-                let x = (gui.line_x[s] + lf * text::WIDTH * gui.scale) * scalex;
-                let y = (gui.line_y[s]) * scaley;
-                vertices.push(Vertex {
-                    pos: Vec3 { x, y, z: 0.0 },
-                    uv: Vec2 {
-                        x: tex_uv.u1,
-                        y: tex_uv.v1,
-                    },
-                    act: gui.line_active[s] as f32,
-                }); // top left
-
-                indices.push(4 * idx + 0);
-                indices.push(4 * idx + 1);
-                indices.push(4 * idx + 2);
-                indices.push(4 * idx + 2);
-                indices.push(4 * idx + 3);
-                indices.push(4 * idx + 0);
-
-                idx = idx + 1;
+            for (pen, glyph) in fonts.layout(&gui.lines[s], gui.scale) {
+                if glyph.width == 0.0 {
+                    continue;
+                }
+                let x = gui.line_x[s] - gui.line_scroll[s] + pen;
+                if let Some((rect, uv)) = glyph_rect(&glyph, x, gui.line_y[s] + ascent, gui.clip(s), scalex, scaley) {
+                    push_quad(&mut vertices, &mut indices, &mut idx, rect, uv, gui.line_active[s] as f32);
+                }
             }
         }
 
+        for button in &widgets.buttons {
+            let (x1, y1, x2, y2) = button.rect;
+            push_quad(&mut vertices, &mut indices, &mut idx, (x1 * scalex, y1 * scaley, x2 * scalex, y2 * scaley), tex_uv, -1.0);
+            let x = 0.5 * (x1 + x2 - fonts.text_width(&button.label, gui.scale));
+            let y = 0.5 * (y1 + y2 - gui.line_height) + ascent;
+            for (pen, glyph) in fonts.layout(&button.label, gui.scale) {
+                if glyph.width == 0.0 {
+                    continue;
+                }
+                if let Some((rect, uv)) = glyph_rect(&glyph, x + pen, y, None, scalex, scaley) {
+                    push_quad(&mut vertices, &mut indices, &mut idx, rect, uv, button.act());
+                }
+            }
+        }
+
+        for slider in widgets.sliders.iter().filter(|s| !s.label.is_empty()) {
+            let (x1, y1, x2, y2) = slider.rect;
+            push_quad(&mut vertices, &mut indices, &mut idx, (x1 * scalex, y1 * scaley, x2 * scalex, y2 * scaley), tex_uv, -1.0);
+            let act = if slider.focused || slider.dragging { 1.0 } else if slider.hover { 0.5 } else { 0.0 };
+            let x = x1 + 0.5 * gui.line_height;
+            let y = 0.5 * (y1 + y2 - gui.line_height) + ascent;
+            for (pen, glyph) in fonts.layout(&slider.label, gui.scale) {
+                if glyph.width == 0.0 {
+                    continue;
+                }
+                if let Some((rect, uv)) = glyph_rect(&glyph, x + pen, y, None, scalex, scaley) {
+                    push_quad(&mut vertices, &mut indices, &mut idx, rect, uv, act);
+                }
+            }
+        }
+
+        Mesh {
+            vertices,
+            indices,
+            num: idx as i32,
+        }
+    }
+
+    pub fn new_waveform(waveform: Option<&analysis::Waveform>, progress: f32, rect: (f32, f32, f32, f32), scalex: f32, scaley: f32) -> Mesh {
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut indices: Vec<i16> = Vec::new();
+        let mut idx = 0;
+
+        let (x1, y1, x2, y2) = rect;
+        let mid = 0.5 * (y1 + y2);
+        let half = 0.5 * (y2 - y1);
+        let dx = (x2 - x1) / settings::WAVEFORM_COLUMNS as f32;
+
+        for l in 0..settings::WAVEFORM_COLUMNS {
+            let (peak, rms) = match waveform {
+                Some(w) => (w.peak[l], w.rms[l]),
+                None => (0.0, 0.0),
+            };
+            let lf = l as f32;
+            let act = if (lf + 0.5) / settings::WAVEFORM_COLUMNS as f32 <= progress { 1.0 } else { 0.0 };
+            let x = x1 + lf * dx;
+
+            // peak envelope is drawn faint (uv.x = 0), RMS body solid (uv.x = 1)
+            let h = (peak * half).max(0.5);
+            push_quad(&mut vertices, &mut indices, &mut idx, (x * scalex, (mid - h) * scaley, (x + 0.8 * dx) * scalex, (mid + h) * scaley), column_uv(0.0), act);
+            let h = (rms * half).max(0.5);
+            push_quad(&mut vertices, &mut indices, &mut idx, (x * scalex, (mid - h) * scaley, (x + 0.8 * dx) * scalex, (mid + h) * scaley), column_uv(1.0), act);
+        }
+
+        let x = x1 + progress.clamp(0.0, 1.0) * (x2 - x1);
+        push_quad(&mut vertices, &mut indices, &mut idx, ((x - 1.0) * scalex, y1 * scaley, (x + 1.0) * scalex, y2 * scaley), column_uv(1.0), 1.0);
+
+        Mesh {
+            vertices,
+            indices,
+            num: idx as i32,
+        }
+    }
+
+    /// Tracks of the sliders: a faint bar, the filled part and a knob,
+    /// drawn with the flat-color pipeline of the waveform.
+    pub fn new_sliders(sliders: &[widgets::Slider], scalex: f32, scaley: f32) -> Mesh {
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut indices: Vec<i16> = Vec::new();
+        let mut idx = 0;
+
+        for slider in sliders {
+            let (x1, y1, x2, y2) = slider.track();
+            let x = x1 + slider.fraction() * (x2 - x1);
+            let h = if slider.focused || slider.dragging || slider.hover { 1.0 } else { 0.5 } * (y2 - y1);
+
+            push_quad(&mut vertices, &mut indices, &mut idx, (x1 * scalex, y1 * scaley, x2 * scalex, y2 * scaley), column_uv(0.0), 0.0);
+            push_quad(&mut vertices, &mut indices, &mut idx, (x1 * scalex, y1 * scaley, x * scalex, y2 * scaley), column_uv(1.0), 1.0);
+            push_quad(&mut vertices, &mut indices, &mut idx, ((x - 2.0) * scalex, (y1 - h) * scaley, (x + 2.0) * scalex, (y2 + h) * scaley), column_uv(1.0), 1.0);
+        }
+
         Mesh {
             vertices,
             indices,
@@ -250,7 +252,8 @@ impl Mesh {
         }
     }
 
-    pub fn new_visuals(stats: &[i16; settings::SAMPLES]) -> Mesh {
+    /// Bar graph of `stats` in `bars` bars, a power of two up to `settings::MAX_BARS`.
+    pub fn new_visuals(stats: &[i16; settings::SAMPLES], gain: f32, bars: usize) -> Mesh {
         let mut vertices: Vec<Vertex> = Vec::new();
         let mut indices: Vec<i16> = Vec::new();
         let mut idx = 0;
@@ -262,25 +265,26 @@ impl Mesh {
         let mut snorm: [f32; settings::SAMPLES] = [0.0; settings::SAMPLES];
 
         for l in 0..settings::SAMPLES {
-            snorm[l] = stats[l].abs() as f32 / smax_f
+            snorm[l] = stats[l].saturating_abs() as f32 / smax_f * gain
         }
 
-        let mut snorm2: [f32; settings::BINS] = [0.0; settings::BINS];
+        let bin_samples = settings::SAMPLES / bars;
+        let mut snorm2: Vec<f32> = vec![0.0; bars];
 
         
 
-        for l in 0..settings::BINS {
-            for k in 0..settings::BIN_SAMPLES {
-                snorm2[l] += snorm[settings::BIN_SAMPLES*l+k] / settings::BIN_SAMPLES as f32;
+        for l in 0..bars {
+            for k in 0..bin_samples {
+                snorm2[l] += snorm[bin_samples*l+k] / bin_samples as f32;
             }
         }
 
-        let x0: f32 = 0.1/settings::BINS as f32;
+        let x0: f32 = 0.1/bars as f32;
 
-        let dx: f32 = 0.8/settings::BINS as f32;
+        let dx: f32 = 0.8/bars as f32;
 
-        for l in 0..settings::BINS {
-            let lf = l as f32 / settings::BINS as f32;
+        for l in 0..bars {
+            let lf = l as f32 / bars as f32;
 
             tex_uv = TextureUV {
                 u1: lf,
@@ -413,3 +417,65 @@ impl Mesh {
         }
     }
 }
+
+fn push_quad(vertices: &mut Vec<Vertex>, indices: &mut Vec<i16>, idx: &mut i16, rect: (f32, f32, f32, f32), uv: TextureUV, act: f32) {
+    let (x1, y1, x2, y2) = rect;
+    vertices.push(Vertex {
+        pos: Vec3 { x: x2, y: y1, z: 0.0 },
+        uv: Vec2 { x: uv.u2, y: uv.v1 },
+        act,
+    }); // top right
+    vertices.push(Vertex {
+        pos: Vec3 { x: x2, y: y2, z: 0.0 },
+        uv: Vec2 { x: uv.u2, y: uv.v2 },
+        act,
+    }); // bottom right
+    vertices.push(Vertex {
+        pos: Vec3 { x: x1, y: y2, z: 0.0 },
+        uv: Vec2 { x: uv.u1, y: uv.v2 },
+        act,
+    }); // bottom left
+    vertices.push(Vertex {
+        pos: Vec3 { x: x1, y: y1, z: 0.0 },
+        uv: Vec2 { x: uv.u1, y: uv.v1 },
+        act,
+    }); // top left
+
+    indices.push(4 * *idx);
+    indices.push(4 * *idx + 1);
+    indices.push(4 * *idx + 2);
+    indices.push(4 * *idx + 2);
+    indices.push(4 * *idx + 3);
+    indices.push(4 * *idx);
+
+    *idx += 1;
+}
+
+/// Vertical strip of texture coordinates at `u`, for the untextured waveform and progress quads.
+fn column_uv(u: f32) -> TextureUV {
+    TextureUV { u1: u, u2: u, v1: 0.0, v2: 1.0 }
+}
+
+/// Screen rectangle and texture coordinates of a glyph with its pen at `x` on the baseline `y`,
+/// snapped to whole pixels and cut to the horizontal `clip` range. `None` when nothing is left.
+fn glyph_rect(glyph: &Glyph, x: f32, y: f32, clip: Option<(f32, f32)>, scalex: f32, scaley: f32) -> Option<((f32, f32, f32, f32), TextureUV)> {
+    let mut x1 = (x + glyph.x).round();
+    let mut x2 = x1 + glyph.width;
+    let y1 = (y + glyph.y).round();
+    let mut uv = glyph.uv;
+    if let Some((left, right)) = clip {
+        let du = (uv.u2 - uv.u1) / glyph.width;
+        if x1 < left {
+            uv.u1 += (left - x1) * du;
+            x1 = left;
+        }
+        if x2 > right {
+            uv.u2 -= (x2 - right) * du;
+            x2 = right;
+        }
+        if x1 >= x2 {
+            return None;
+        }
+    }
+    Some(((x1 * scalex, y1 * scaley, x2 * scalex, (y1 + glyph.height) * scaley), uv))
+}
@@ -0,0 +1,460 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use zbus::blocking::connection;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+
+use crate::command::Command;
+use crate::settings;
+use crate::tags;
+use crate::State;
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.small_rust_music_player";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const TRACK_PATH: &str = "/org/small_rust_music_player/track";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// `org.mpris.MediaPlayer2`: who we are. The player has no window management to offer.
+struct Root;
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        "Small Rust Music Player".to_string()
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        ["audio/mpeg", "audio/flac", "audio/ogg", "audio/wav"].iter().map(|m| m.to_string()).collect()
+    }
+}
+
+/// `org.mpris.MediaPlayer2.Player`, a thin layer over `Command` and `State`.
+struct Player {
+    state: Arc<Mutex<State>>,
+}
+
+impl Player {
+    fn command(&self, command: Command) {
+        command.apply(&mut self.state.lock().unwrap());
+    }
+}
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) {
+        self.command(Command::Next);
+    }
+
+    fn previous(&self) {
+        self.command(Command::Previous);
+    }
+
+    fn pause(&self) {
+        self.command(Command::Pause);
+    }
+
+    fn play_pause(&self) {
+        self.command(Command::PlayPause);
+    }
+
+    fn stop(&self) {
+        self.command(Command::Stop);
+    }
+
+    fn play(&self) {
+        self.command(Command::Play);
+    }
+
+    /// `offset` in microseconds.
+    fn seek(&self, offset: i64) {
+        self.command(Command::SeekBy(offset as f64 / 1e6));
+    }
+
+    /// Ignored unless `track_id` is still the current track, as the specification asks.
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+        let current = track_id_of(&self.state.lock().unwrap());
+        if track_id.as_str() == current {
+            self.command(Command::Seek(position as f64 / 1e6));
+        }
+    }
+
+    fn open_uri(&self, _uri: String) {}
+
+    #[zbus(signal)]
+    async fn seeked(emitter: &SignalEmitter<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        status(&self.state.lock().unwrap()).to_string()
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn set_rate(&self, _rate: f64) {}
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        metadata(&self.state.lock().unwrap())
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.state.lock().unwrap().volume as f64
+    }
+
+    #[zbus(property)]
+    fn set_volume(&self, volume: f64) {
+        self.command(Command::Volume(volume as f32));
+    }
+
+    /// In microseconds; clients poll it, changes are not signalled.
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        (self.state.lock().unwrap().position * 1e6) as i64
+    }
+
+    /// `Command::Next` and `Command::Previous` only act while playing.
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        self.state.lock().unwrap().play
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        self.state.lock().unwrap().play
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        !self.state.lock().unwrap().playlist.is_empty()
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        self.state.lock().unwrap().duration > 0.0
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+fn status(state: &State) -> &'static str {
+    if state.playlist.is_empty() || state.stopped {
+        "Stopped"
+    } else if state.play {
+        "Playing"
+    } else {
+        "Paused"
+    }
+}
+
+fn track_id_of(state: &State) -> String {
+    if state.file_path.as_os_str().is_empty() {
+        NO_TRACK.to_string()
+    } else {
+        format!("{}/{}", TRACK_PATH, state.track_index)
+    }
+}
+
+fn metadata(state: &State) -> HashMap<String, OwnedValue> {
+    let mut map = HashMap::new();
+    let mut insert = |key: &str, value: Value| {
+        if let Ok(value) = OwnedValue::try_from(value) {
+            map.insert(key.to_string(), value);
+        }
+    };
+    let track_id = track_id_of(state);
+    if let Ok(path) = ObjectPath::try_from(track_id.as_str()) {
+        insert("mpris:trackid", Value::from(path));
+    }
+    if track_id == NO_TRACK {
+        return map;
+    }
+    insert("mpris:length", Value::from((state.duration * 1e6) as i64));
+    insert("xesam:url", Value::from(file_url(&state.file_path)));
+    let tags = state.tags.get(&state.file_path).cloned().unwrap_or_default();
+    let title = if tags.title.is_empty() { state.file_name.clone() } else { tags.title };
+    insert("xesam:title", Value::from(title));
+    if !tags.artist.is_empty() {
+        insert("xesam:artist", Value::from(vec![tags.artist]));
+    }
+    if !tags.album.is_empty() {
+        insert("xesam:album", Value::from(tags.album));
+    }
+    if let Some(art) = tags::cover_art(&state.file_path) {
+        insert("mpris:artUrl", Value::from(file_url(&art)));
+    }
+    map
+}
+
+/// `file://` URL with everything but unreserved characters and slashes percent-encoded.
+pub fn file_url(path: &Path) -> String {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut url = "file://".to_string();
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            url.push(byte as char);
+        } else {
+            url.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    url
+}
+
+/// What the poll loop compares to find out which properties changed.
+#[derive(PartialEq)]
+struct Snapshot {
+    status: &'static str,
+    track: PathBuf,
+    tagged: bool,
+    volume: f32,
+    duration: f64,
+}
+
+impl Snapshot {
+    fn take(state: &State) -> Snapshot {
+        Snapshot {
+            status: status(state),
+            track: state.file_path.clone(),
+            tagged: state.tags.contains_key(&state.file_path),
+            volume: state.volume,
+            duration: state.duration,
+        }
+    }
+}
+
+/// Serves the player on the session bus from a background thread, polling `State`
+/// every `settings::MPRIS_POLL_TIME` seconds to signal changes. Without a session bus
+/// the player carries on and says so in a notification.
+pub fn spawn(state: Arc<Mutex<State>>) {
+    thread::spawn(move || {
+        if let Err(error) = connection::Builder::session().and_then(|bus| serve(Arc::clone(&state), bus)) {
+            state.lock().unwrap().notifications.warning(format!("mpris: {}", error));
+        }
+    });
+}
+
+/// Serves on the bus that `bus` connects to; returns only on an error.
+fn serve(state: Arc<Mutex<State>>, bus: connection::Builder<'_>) -> zbus::Result<()> {
+    let player = Player { state: Arc::clone(&state) };
+    let connection = bus
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Root)?
+        .serve_at(OBJECT_PATH, player)?
+        .build()?;
+    let player = connection.object_server().interface::<_, Player>(OBJECT_PATH)?;
+    let emitter = player.signal_emitter();
+
+    let mut last = Snapshot::take(&state.lock().unwrap());
+    let mut last_position = state.lock().unwrap().position;
+    let mut last_time = Instant::now();
+    loop {
+        thread::sleep(Duration::from_secs_f64(settings::MPRIS_POLL_TIME));
+        let s_mpris = state.lock().unwrap();
+        let now = Snapshot::take(&s_mpris);
+        let position = s_mpris.position;
+        let playing = s_mpris.play;
+        drop(s_mpris);
+
+        let iface = player.get();
+        if now.status != last.status {
+            zbus::block_on(iface.playback_status_changed(emitter))?;
+            zbus::block_on(iface.can_play_changed(emitter))?;
+            zbus::block_on(iface.can_go_next_changed(emitter))?;
+            zbus::block_on(iface.can_go_previous_changed(emitter))?;
+        }
+        if now.track != last.track || now.tagged != last.tagged || now.duration != last.duration {
+            zbus::block_on(iface.metadata_changed(emitter))?;
+            zbus::block_on(iface.can_seek_changed(emitter))?;
+        }
+        if now.volume != last.volume {
+            zbus::block_on(iface.volume_changed(emitter))?;
+        }
+        // a jump away from where playback should be by now is a seek
+        let elapsed = if playing { last_time.elapsed().as_secs_f64() } else { 0.0 };
+        if now.track == last.track && (position - last_position - elapsed).abs() > settings::SEEK_JUMP {
+            zbus::block_on(Player::seeked(emitter, (position * 1e6) as i64))?;
+        }
+        drop(iface);
+
+        last = now;
+        last_position = position;
+        last_time = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zbus::blocking::fdo::PropertiesProxy;
+    use zbus::names::InterfaceName;
+
+    use crate::config::Config;
+    use crate::test_bus::{eventually, TestBus};
+
+    const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+
+    fn playing() -> State {
+        let mut state = State::new(Config::default());
+        let track = PathBuf::from("/music/some album/01 track.flac");
+        state.playlist = vec![track.clone(), PathBuf::from("/music/some album/02 track.flac")];
+        state.file_path = track.clone();
+        state.file_name = "01 track".to_string();
+        state.duration = 200.0;
+        state.position = 30.0;
+        state.tags.insert(track, tags::Tags {
+            title: "Title".to_string(),
+            artist: "Artist".to_string(),
+            album: String::new(),
+            track: "1".to_string(),
+        });
+        state
+    }
+
+    fn call<B: serde::Serialize + zbus::zvariant::DynamicType>(client: &zbus::blocking::Connection, method: &str, body: &B) {
+        client.call_method(Some(BUS_NAME), OBJECT_PATH, Some(PLAYER), method, body).unwrap();
+    }
+
+    fn text(map: &HashMap<String, OwnedValue>, key: &str) -> String {
+        map[key].downcast_ref::<String>().unwrap()
+    }
+
+    #[test]
+    fn status_follows_play_and_stop() {
+        let mut state = playing();
+        assert_eq!(status(&state), "Playing");
+        Command::Pause.apply(&mut state);
+        assert_eq!(status(&state), "Paused");
+        Command::Stop.apply(&mut state);
+        assert_eq!(status(&state), "Stopped");
+        Command::Pause.apply(&mut state);
+        assert_eq!(status(&state), "Stopped");
+        Command::PlayPause.apply(&mut state);
+        assert_eq!(status(&state), "Playing");
+        state.playlist.clear();
+        assert_eq!(status(&state), "Stopped");
+    }
+
+    #[test]
+    fn metadata_of_a_track() {
+        let state = playing();
+        let map = metadata(&state);
+        assert_eq!(text(&map, "xesam:title"), "Title");
+        assert_eq!(map["xesam:artist"].downcast_ref::<zbus::zvariant::Array>().unwrap().len(), 1);
+        assert!(!map.contains_key("xesam:album"));
+        assert_eq!(map["mpris:length"].downcast_ref::<i64>().unwrap(), 200_000_000);
+        assert_eq!(text(&map, "xesam:url"), "file:///music/some%20album/01%20track.flac");
+        let id = map["mpris:trackid"].downcast_ref::<ObjectPath>().unwrap();
+        assert_eq!(id.as_str(), format!("{}/0", TRACK_PATH));
+    }
+
+    #[test]
+    fn metadata_without_a_track() {
+        let state = State::new(Config::default());
+        let map = metadata(&state);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map["mpris:trackid"].downcast_ref::<ObjectPath>().unwrap().as_str(), NO_TRACK);
+    }
+
+    #[test]
+    fn file_urls_are_percent_encoded() {
+        assert_eq!(file_url(Path::new("/no/such dir/ä#1.mp3")), "file:///no/such%20dir/%C3%A4%231.mp3");
+        assert_eq!(file_url(Path::new("/no/such/a-b_c.~d")), "file:///no/such/a-b_c.~d");
+    }
+
+    #[test]
+    fn player_on_a_private_bus() {
+        let Some(bus) = TestBus::start() else {
+            return;
+        };
+        let state = Arc::new(Mutex::new(playing()));
+        let served = Arc::clone(&state);
+        let builder = bus.connect();
+        thread::spawn(move || serve(served, builder));
+
+        let client = bus.connect().build().unwrap();
+        let properties = PropertiesProxy::builder(&client).destination(BUS_NAME).unwrap().path(OBJECT_PATH).unwrap().build().unwrap();
+        let interface = InterfaceName::from_static_str(PLAYER).unwrap();
+        let get = |name: &str| properties.get(interface.clone(), name);
+        assert!(eventually(|| get("PlaybackStatus").is_ok()), "the player never showed up");
+        let status = || String::try_from(get("PlaybackStatus").unwrap()).unwrap();
+        let flag = |name: &str| bool::try_from(get(name).unwrap()).unwrap();
+
+        assert_eq!(status(), "Playing");
+        assert!(flag("CanGoNext") && flag("CanSeek"));
+        assert_eq!(i64::try_from(get("Position").unwrap()).unwrap(), 30_000_000);
+
+        call(&client, "Next", &());
+        assert!(state.lock().unwrap().skip);
+        call(&client, "Seek", &10_000_000i64);
+        assert_eq!(state.lock().unwrap().seek, Some(40.0));
+        call(&client, "SetPosition", &(ObjectPath::try_from(NO_TRACK).unwrap(), 5_000_000i64));
+        assert_eq!(state.lock().unwrap().seek, Some(40.0), "a stale track id is ignored");
+        call(&client, "SetPosition", &(ObjectPath::try_from(format!("{}/0", TRACK_PATH)).unwrap(), 5_000_000i64));
+        assert_eq!(state.lock().unwrap().seek, Some(5.0));
+
+        call(&client, "Pause", &());
+        assert_eq!(status(), "Paused");
+        assert!(!flag("CanGoNext") && !flag("CanGoPrevious"));
+        call(&client, "Stop", &());
+        assert_eq!(status(), "Stopped");
+        call(&client, "PlayPause", &());
+        assert_eq!(status(), "Playing");
+
+        properties.set(interface.clone(), "Volume", Value::from(0.25f64)).unwrap();
+        assert_eq!(state.lock().unwrap().volume, 0.25);
+
+        let metadata: HashMap<String, OwnedValue> = get("Metadata").unwrap().try_into().unwrap();
+        assert_eq!(text(&metadata, "xesam:title"), "Title");
+    }
+}
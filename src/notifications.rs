@@ -0,0 +1,99 @@
+use std::time::Instant;
+
+use crate::settings;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// Seconds a toast of this severity stays on screen, fade included.
+    pub fn timeout(&self) -> f64 {
+        match self {
+            Severity::Info => settings::TOAST_INFO_TIME,
+            Severity::Warning => settings::TOAST_WARNING_TIME,
+            Severity::Error => settings::TOAST_ERROR_TIME,
+        }
+    }
+
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Severity::Info => "",
+            Severity::Warning => "⚠ ",
+            Severity::Error => "✖ ",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub text: String,
+    pub severity: Severity,
+    pub created: Instant,
+}
+
+impl Notification {
+    pub fn age(&self, now: Instant) -> f64 {
+        now.saturating_duration_since(self.created).as_secs_f64()
+    }
+
+    /// Fully opaque until the last `settings::TOAST_FADE` seconds of its timeout.
+    pub fn alpha(&self, now: Instant) -> f32 {
+        let left = self.severity.timeout() - self.age(now);
+        (left / settings::TOAST_FADE).clamp(0.0, 1.0) as f32
+    }
+
+    /// The part before the first colon, e.g. `volume` for "volume: 40%".
+    fn topic(&self) -> &str {
+        self.text.split(':').next().unwrap_or_default()
+    }
+}
+
+/// Messages for the overlay, oldest first. Both the stage and the audio thread push into it
+/// through `State`; the stage drops them once they timed out.
+#[derive(Debug, Clone, Default)]
+pub struct Notifications {
+    queue: Vec<Notification>,
+}
+
+impl Notifications {
+    /// A message on the same topic as the newest one replaces it,
+    /// so dragging a slider keeps updating one toast instead of stacking them.
+    pub fn push(&mut self, severity: Severity, text: String) {
+        let notification = Notification {
+            text,
+            severity,
+            created: Instant::now(),
+        };
+        if let Some(last) = self.queue.last() {
+            if last.severity == severity && last.topic() == notification.topic() {
+                self.queue.pop();
+            }
+        }
+        self.queue.push(notification);
+        if self.queue.len() > settings::MAX_TOASTS {
+            self.queue.remove(0);
+        }
+    }
+
+    pub fn info(&mut self, text: String) {
+        self.push(Severity::Info, text);
+    }
+
+    pub fn warning(&mut self, text: String) {
+        self.push(Severity::Warning, text);
+    }
+
+    pub fn error(&mut self, text: String) {
+        self.push(Severity::Error, text);
+    }
+
+    /// Drops the notifications that timed out and returns the rest.
+    pub fn visible(&mut self, now: Instant) -> &[Notification] {
+        self.queue.retain(|n| n.age(now) < n.severity.timeout());
+        &self.queue
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use zbus::blocking::Connection;
+use zbus::zvariant::Value;
+
+use crate::mpris;
+use crate::settings;
+use crate::status::Status;
+use crate::tags;
+use crate::State;
+
+const SERVICE: &str = "org.freedesktop.Notifications";
+const OBJECT_PATH: &str = "/org/freedesktop/Notifications";
+
+/// The desktop's notification server, `org.freedesktop.Notifications` on the session bus.
+struct Server {
+    connection: Connection,
+    /// Whether the server reads the body as markup, which then needs escaping.
+    markup: bool,
+    /// The notification shown last; the next one replaces it rather than piling up.
+    id: u32,
+}
+
+impl Server {
+    fn connect(connection: Connection) -> zbus::Result<Server> {
+        let reply = connection.call_method(Some(SERVICE), OBJECT_PATH, Some(SERVICE), "GetCapabilities", &())?;
+        let capabilities: Vec<String> = reply.body().deserialize()?;
+        Ok(Server {
+            connection,
+            markup: capabilities.iter().any(|c| c == "body-markup"),
+            id: 0,
+        })
+    }
+
+    /// Shows the title, with the artist and album below and the folder's cover art when there is one.
+    fn announce(&mut self, status: &Status) -> zbus::Result<()> {
+        let body: Vec<&str> = [status.artist.as_str(), status.album.as_str()].into_iter().filter(|s| !s.is_empty()).collect();
+        let body = body.join("\n");
+        let body = if self.markup { escape(&body) } else { body };
+        let mut hints: HashMap<&str, Value> = HashMap::new();
+        if let Some(art) = tags::cover_art(status.path.as_ref()) {
+            hints.insert("image-path", Value::from(mpris::file_url(&art)));
+        }
+        let reply = self.connection.call_method(
+            Some(SERVICE),
+            OBJECT_PATH,
+            Some(SERVICE),
+            "Notify",
+            &(settings::APP_NAME, self.id, "audio-x-generic", status.title.as_str(), body.as_str(), Vec::<&str>::new(), hints, -1i32),
+        )?;
+        self.id = reply.body().deserialize()?;
+        Ok(())
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Sends a desktop notification when a new track starts playing, unless turned off
+/// in the configuration. A track is only announced after `settings::NOTIFY_SETTLE_TIME`
+/// seconds, so skipping through several in a row gives one notification.
+pub fn spawn(state: Arc<Mutex<State>>) {
+    thread::spawn(move || announce_tracks(state, Connection::session));
+}
+
+/// Announces to the server on the bus that `bus` connects to; never returns.
+fn announce_tracks(state: Arc<Mutex<State>>, bus: impl Fn() -> zbus::Result<Connection>) {
+    let mut server: Option<Server> = None;
+    let mut failing = false;
+    let mut announced = String::new();
+    let mut current = String::new();
+    let mut since = Instant::now();
+    loop {
+        thread::sleep(Duration::from_secs_f64(settings::NOTIFY_POLL_TIME));
+        let s_notify = state.lock().unwrap();
+        let enabled = s_notify.config.notifications.enabled;
+        let status = Status::of(&s_notify);
+        drop(s_notify);
+
+        if status.path != current {
+            current = status.path.clone();
+            since = Instant::now();
+        }
+        if !enabled {
+            // turning them on again should not announce a track that started meanwhile
+            announced = current.clone();
+            continue;
+        }
+        if status.state != "playing" || current.is_empty() || current == announced
+            || since.elapsed().as_secs_f64() < settings::NOTIFY_SETTLE_TIME {
+            continue;
+        }
+        announced = current.clone();
+
+        let result = match server.as_mut() {
+            Some(server) => server.announce(&status),
+            None => bus().and_then(Server::connect).and_then(|mut connected| {
+                let result = connected.announce(&status);
+                server = Some(connected);
+                result
+            }),
+        };
+        match result {
+            Ok(()) => failing = false,
+            Err(error) => {
+                // connect again next time, the server may have been restarted
+                server = None;
+                if !failing {
+                    state.lock().unwrap().notifications.warning(format!("desktop notifications: {}", error));
+                    failing = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use zbus::blocking::connection;
+    use zbus::zvariant::OwnedValue;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::test_bus::{eventually, TestBus};
+
+    /// What the stand-in server was asked to show.
+    #[derive(Debug, Clone)]
+    struct Shown {
+        replaces: u32,
+        summary: String,
+        body: String,
+        image: Option<String>,
+    }
+
+    /// A notification server that shows nothing and keeps what it was asked to show.
+    struct Daemon {
+        shown: Arc<Mutex<Vec<Shown>>>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl Daemon {
+        fn get_capabilities(&self) -> Vec<String> {
+            vec!["body".to_string(), "body-markup".to_string()]
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            _app_name: String,
+            replaces_id: u32,
+            _app_icon: String,
+            summary: String,
+            body: String,
+            _actions: Vec<String>,
+            hints: HashMap<String, OwnedValue>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let mut shown = self.shown.lock().unwrap();
+            shown.push(Shown {
+                replaces: replaces_id,
+                summary,
+                body,
+                image: hints.get("image-path").and_then(|v| v.downcast_ref::<String>().ok()),
+            });
+            shown.len() as u32
+        }
+    }
+
+    /// Starts playing the track `name` in `dir`.
+    fn play(state: &Mutex<State>, dir: &std::path::Path, name: &str) {
+        let mut state = state.lock().unwrap();
+        state.file_path = dir.join(format!("{}.flac", name));
+        state.file_name = name.to_string();
+        state.play = true;
+    }
+
+    #[test]
+    fn markup_is_escaped() {
+        assert_eq!(escape("Tom & Jerry <live>"), "Tom &amp; Jerry &lt;live&gt;");
+        assert_eq!(escape("plain"), "plain");
+    }
+
+    #[test]
+    fn tracks_announced_on_a_private_bus() {
+        let Some(bus) = TestBus::start() else {
+            return;
+        };
+        let shown = Arc::new(Mutex::new(Vec::new()));
+        let _daemon = bus.connect()
+            .name(SERVICE).unwrap()
+            .serve_at(OBJECT_PATH, Daemon { shown: Arc::clone(&shown) }).unwrap()
+            .build().unwrap();
+
+        let dir = bus.dir.join("album");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("cover.jpg"), b"").unwrap();
+
+        let mut state = State::new(Config::default());
+        state.playlist = ["a", "b", "c", "d", "e"].iter().map(|n| dir.join(format!("{}.flac", n))).collect();
+        state.tags.insert(dir.join("c.flac"), tags::Tags {
+            title: "Title".to_string(),
+            artist: "Tom & Jerry".to_string(),
+            album: "Album".to_string(),
+            track: "3".to_string(),
+        });
+        let state = Arc::new(Mutex::new(state));
+        play(&state, &dir, "a");
+        let announcing = Arc::clone(&state);
+        let address = bus.address.clone();
+        thread::spawn(move || announce_tracks(announcing, move || connection::Builder::address(address.as_str())?.build()));
+
+        // skipping through a and b before they settle only announces c
+        thread::sleep(Duration::from_secs_f64(settings::NOTIFY_SETTLE_TIME / 3.0));
+        play(&state, &dir, "b");
+        thread::sleep(Duration::from_secs_f64(settings::NOTIFY_SETTLE_TIME / 3.0));
+        play(&state, &dir, "c");
+        assert!(eventually(|| !shown.lock().unwrap().is_empty()), "nothing was announced");
+        thread::sleep(Duration::from_secs_f64(settings::NOTIFY_SETTLE_TIME * 1.5));
+        let first = shown.lock().unwrap().clone();
+        assert_eq!(first.len(), 1, "{:?}", first);
+        assert_eq!(first[0].replaces, 0);
+        assert_eq!(first[0].summary, "Title");
+        assert_eq!(first[0].body, "Tom &amp; Jerry\nAlbum");
+        assert_eq!(first[0].image, Some(mpris::file_url(&dir.join("cover.jpg"))));
+
+        // the next one takes the place of the last
+        play(&state, &dir, "d");
+        assert!(eventually(|| shown.lock().unwrap().len() == 2), "d was not announced");
+        let second = shown.lock().unwrap()[1].clone();
+        assert_eq!(second.replaces, 1);
+        assert_eq!(second.summary, "d");
+        assert_eq!(second.body, "");
+
+        // turned off, nothing more
+        state.lock().unwrap().config.notifications.enabled = false;
+        play(&state, &dir, "e");
+        thread::sleep(Duration::from_secs_f64(settings::NOTIFY_SETTLE_TIME * 2.0));
+        assert_eq!(shown.lock().unwrap().len(), 2);
+    }
+}
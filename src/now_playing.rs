@@ -0,0 +1,124 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::settings;
+use crate::status::Status;
+use crate::text;
+use crate::State;
+
+const TEXT_FILE: &str = "now-playing.txt";
+const JSON_FILE: &str = "now-playing.json";
+
+/// Keeps `now-playing.txt`, filled in from `template`, and `now-playing.json`, the status of
+/// the control socket, up to date in `dir` for status bars and streaming software. Files are
+/// only rewritten when their content changes; the position counts in whole seconds.
+pub fn spawn(state: Arc<Mutex<State>>, dir: PathBuf, template: String) {
+    thread::spawn(move || {
+        let mut last_text = String::new();
+        let mut last_json = String::new();
+        let mut failing = false;
+        loop {
+            let mut status = Status::of(&state.lock().unwrap());
+            status.position = status.position.floor();
+            status.duration = status.duration.floor();
+            let text = fill(&template, &status) + "\n";
+            let json = serde_json::to_string_pretty(&status).unwrap_or_default() + "\n";
+
+            if text != last_text || json != last_json {
+                match write(&dir, &text, &json) {
+                    Ok(()) => {
+                        last_text = text;
+                        last_json = json;
+                        failing = false;
+                    }
+                    Err(error) if !failing => {
+                        let text = format!("now playing: {}: {}", dir.display(), error);
+                        state.lock().unwrap().notifications.warning(text);
+                        failing = true;
+                    }
+                    Err(_) => {}
+                }
+            }
+            thread::sleep(Duration::from_secs_f64(settings::NOW_PLAYING_POLL_TIME));
+        }
+    });
+}
+
+fn write(dir: &Path, text: &str, json: &str) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    settings::write_atomic(&dir.join(TEXT_FILE), text.as_bytes())?;
+    settings::write_atomic(&dir.join(JSON_FILE), json.as_bytes())
+}
+
+/// Replaces `{artist}`, `{title}`, `{album}`, `{elapsed}`, `{total}`, `{state}` and `{file}`
+/// in one pass, so braces in the tags stay as they are; anything else in braces is kept too.
+/// Times count in whole seconds.
+fn fill(template: &str, status: &Status) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        filled.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let Some(close) = rest.find('}') else {
+            break;
+        };
+        let value = match &rest[1..close] {
+            "artist" => status.artist.clone(),
+            "title" => status.title.clone(),
+            "album" => status.album.clone(),
+            "elapsed" => text::time_string(status.position.floor()),
+            "total" => text::time_string(status.duration.floor()),
+            "state" => status.state.to_string(),
+            "file" => Path::new(&status.path).file_name().unwrap_or_default().to_string_lossy().to_string(),
+            _ => {
+                // not a placeholder; a `{` inside may still start one
+                filled.push('{');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        filled.push_str(&value);
+        rest = &rest[close + 1..];
+    }
+    filled.push_str(rest);
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PlayMode;
+
+    fn status() -> Status {
+        Status {
+            state: "playing",
+            index: 0,
+            path: "/music/Artist/01 Song.flac".to_string(),
+            title: "Song {live}".to_string(),
+            artist: "Artist {title}".to_string(),
+            album: "Album".to_string(),
+            position: 61.9,
+            duration: 3599.99,
+            volume: 1.0,
+            play_mode: PlayMode::Repeat,
+            queue: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn fills_every_placeholder() {
+        assert_eq!(
+            fill("{artist} - {title} [{album}] {elapsed}/{total} {state} {file}", &status()),
+            "Artist {title} - Song {live} [Album] 01:01/59:59 playing 01 Song.flac",
+        );
+    }
+
+    #[test]
+    fn keeps_other_braces() {
+        assert_eq!(fill("{year} {} {{title}} {title", &status()), "{year} {} {Song {live}} {title");
+        assert_eq!(fill("no placeholders", &status()), "no placeholders");
+    }
+}
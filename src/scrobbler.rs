@@ -0,0 +1,91 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::history::Play;
+use crate::settings;
+
+const HEADER: &str = concat!("#AUDIOSCROBBLER/1.1\n#TZ/UTC\n#CLIENT/small-rust-music-player ", env!("CARGO_PKG_VERSION"), "\n");
+
+/// Played for half its length or `settings::SCROBBLE_TIME`, whichever comes first.
+pub fn counts(play: &Play) -> bool {
+    let threshold = if play.duration > 0.0 { (play.duration / 2.0).min(settings::SCROBBLE_TIME) } else { settings::SCROBBLE_TIME };
+    play.listened >= threshold
+}
+
+/// `.scrobbler.log` in the data directory, which Rockbox and the tools that upload
+/// its logs to Last.fm or ListenBrainz know.
+pub fn log_path() -> PathBuf {
+    settings::data_dir().join(".scrobbler.log")
+}
+
+/// Appends a line for a track that counts as played: rated `L` (listened) when it
+/// played to its end, `S` (skipped) when left earlier. Tracks without an artist or
+/// title tag are left out, as the services would refuse them.
+pub fn record(log: &Path, play: &Play) -> io::Result<()> {
+    if !counts(play) || play.artist.is_empty() || play.title.is_empty() {
+        return Ok(());
+    }
+    if let Some(dir) = log.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(log)?;
+    if file.metadata()?.len() == 0 {
+        file.write_all(HEADER.as_bytes())?;
+    }
+    let track = play.track.split('/').next().unwrap_or_default();
+    let line = [
+        field(&play.artist),
+        field(&play.album),
+        field(&play.title),
+        field(track),
+        format!("{}", play.duration.round() as u64),
+        (if play.completed { "L" } else { "S" }).to_string(),
+        format!("{}", play.time),
+        String::new(),
+    ].join("\t");
+    file.write_all(format!("{}\n", line).as_bytes())
+}
+
+/// Tabs and line breaks would break the columns.
+fn field(text: &str) -> String {
+    text.replace(['\t', '\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(title: &str, listened: f64, completed: bool) -> Play {
+        Play {
+            time: 1_700_000_000,
+            path: PathBuf::from("/music/a.mp3"),
+            artist: "Artist".to_string(),
+            title: title.to_string(),
+            album: "Album".to_string(),
+            track: "3/12".to_string(),
+            duration: 200.0,
+            listened,
+            completed,
+        }
+    }
+
+    #[test]
+    fn rates_listened_and_skipped_plays() {
+        let log = std::env::temp_dir().join(format!("srmp-scrobbler-{}.log", std::process::id()));
+        let _ = fs::remove_file(&log);
+        record(&log, &play("Whole", 200.0, true)).unwrap();
+        record(&log, &play("Left\tlate", 150.0, false)).unwrap();
+        // below the threshold, not logged
+        record(&log, &play("Early", 20.0, false)).unwrap();
+        let text = fs::read_to_string(&log).unwrap();
+        let _ = fs::remove_file(&log);
+
+        assert!(text.starts_with(HEADER));
+        let lines: Vec<Vec<&str>> = text[HEADER.len()..].lines().map(|l| l.split('\t').collect()).collect();
+        assert_eq!(lines, vec![
+            vec!["Artist", "Album", "Whole", "3", "200", "L", "1700000000", ""],
+            vec!["Artist", "Album", "Left late", "3", "200", "S", "1700000000", ""],
+        ]);
+    }
+}
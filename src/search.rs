@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::tags::Tags;
+
+/// Fuzzy match of `query` against `text`: every query character has to appear in order,
+/// ignoring case. Higher scores for runs of consecutive characters and word starts,
+/// lower for gaps. `None` when the query does not match.
+pub fn score(query: &str, text: &str) -> Option<i32> {
+    let text: Vec<char> = text.chars().flat_map(|c| c.to_lowercase()).collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for q in query.chars().flat_map(|c| c.to_lowercase()) {
+        if q.is_whitespace() {
+            continue;
+        }
+        let found = position + text[position..].iter().position(|&c| c == q)?;
+        score += 1;
+        match previous {
+            Some(p) if p + 1 == found => score += 5,
+            Some(p) => score -= ((found - p).min(10) / 2) as i32,
+            None => {}
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+/// Indices of the playlist entries matching `query` on the file name or the tags,
+/// best match first; the whole playlist in order for an empty query.
+pub fn filter(query: &str, playlist: &[PathBuf], tags: &HashMap<PathBuf, Tags>) -> Vec<usize> {
+    if query.trim().is_empty() {
+        return (0..playlist.len()).collect();
+    }
+    let mut hits: Vec<(i32, usize)> = Vec::new();
+    for (i, path) in playlist.iter().enumerate() {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut best = score(query, &name);
+        if let Some(tags) = tags.get(path) {
+            for field in [&tags.title, &tags.artist, &tags.album] {
+                best = best.max(score(query, field));
+            }
+        }
+        if let Some(best) = best {
+            hits.push((best, i));
+        }
+    }
+    // stable, so equal scores keep the playlist order
+    hits.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    hits.into_iter().map(|(_, i)| i).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_in_order_ignoring_case() {
+        assert!(score("abc", "a big cat").is_some());
+        assert!(score("cba", "a big cat").is_none());
+        assert!(score("abcd", "abc").is_none());
+        assert_eq!(score("ABC", "abc"), score("abc", "ABC"));
+        assert_eq!(score("ÄÖ", "äö"), Some(10));
+        // spaces in the query do not have to match
+        assert_eq!(score("a b", "ab"), score("ab", "ab"));
+    }
+
+    #[test]
+    fn prefers_runs_and_word_starts() {
+        assert_eq!(score("abc", "abc"), Some(16));
+        assert_eq!(score("abc", "a-b-c"), Some(10));
+        assert_eq!(score("abc", "xaxbxc"), Some(1));
+        assert!(score("so", "the song") > score("so", "reason"));
+        assert!(score("ab", "ab_______") > score("ab", "a________b"));
+    }
+
+    #[test]
+    fn sorts_best_first_keeping_the_playlist_order() {
+        let playlist: Vec<PathBuf> = ["x/moon river.mp3", "x/blue moon.mp3", "x/sun.mp3", "x/moon.mp3", "x/track01.mp3"]
+            .iter().map(PathBuf::from).collect();
+        let mut tags = HashMap::new();
+        tags.insert(playlist[4].clone(), Tags { title: "Harvest Moon".to_string(), ..Tags::default() });
+        // every moon starts a word, so the hits tie and keep their order
+        assert_eq!(filter("moon", &playlist, &tags), vec![0, 1, 3, 4]);
+        assert_eq!(filter("bm", &playlist, &tags), vec![1]);
+        assert_eq!(filter("  ", &playlist, &tags), vec![0, 1, 2, 3, 4]);
+        assert_eq!(filter("su", &playlist, &tags), vec![2]);
+    }
+}
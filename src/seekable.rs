@@ -0,0 +1,175 @@
+use std::fs::File;
+use std::time::Duration;
+use rodio::source::Source;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::{Time, TimeBase};
+
+/// A symphonia decoder as a rodio source. Unlike rodio's own decoder it can seek,
+/// jumping in the stream instead of decoding everything before the target.
+pub struct SeekableDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    buffer: Option<SampleBuffer<i16>>,
+    index: usize,
+    /// Samples still to drop after a seek, from the packet start to the target.
+    skip: u64,
+    channels: u16,
+    sample_rate: u32,
+    duration: Option<Duration>,
+}
+
+impl SeekableDecoder {
+    /// `extension` helps the probe; `None` for a file symphonia cannot decode.
+    pub fn open(file: File, extension: &str) -> Option<SeekableDecoder> {
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        hint.with_extension(extension);
+        let probed = symphonia::default::get_probe()
+            .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+            .ok()?;
+        let format = probed.format;
+        let track = format.default_track()?;
+        let params = track.codec_params.clone();
+        let decoder = symphonia::default::get_codecs().make(&params, &DecoderOptions::default()).ok()?;
+        let duration = params.n_frames.zip(params.time_base).map(|(frames, base)| {
+            let time = base.calc_time(frames);
+            Duration::from_secs_f64(time.seconds as f64 + time.frac)
+        });
+        Some(SeekableDecoder {
+            track_id: track.id,
+            format,
+            decoder,
+            time_base: params.time_base,
+            buffer: None,
+            index: 0,
+            skip: 0,
+            channels: params.channels?.count() as u16,
+            sample_rate: params.sample_rate?,
+            duration,
+        })
+    }
+
+    /// Moves a decoder just opened to `seconds` into the track and returns where
+    /// playback now is. Where the container cannot seek, e.g. past its end, the
+    /// samples before `seconds` are decoded and dropped instead.
+    pub fn seek(&mut self, seconds: f64) -> f64 {
+        if seconds <= 0.0 {
+            return 0.0;
+        }
+        let to = SeekTo::Time { time: Time::from(seconds), track_id: Some(self.track_id) };
+        let (Ok(seeked), Some(base)) = (self.format.seek(SeekMode::Accurate, to), self.time_base) else {
+            self.skip = (seconds * self.sample_rate as f64) as u64 * self.channels as u64;
+            return seconds;
+        };
+        self.decoder.reset();
+        self.buffer = None;
+        self.index = 0;
+        let seconds = |ts| {
+            let time = base.calc_time(ts);
+            time.seconds as f64 + time.frac
+        };
+        let reached = seconds(seeked.required_ts);
+        let early = reached - seconds(seeked.actual_ts);
+        self.skip = (early * self.sample_rate as f64).round() as u64 * self.channels as u64;
+        reached
+    }
+
+    /// Decodes the next packet of the track into the buffer; `false` at the end.
+    fn decode(&mut self) -> bool {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) if packet.track_id() == self.track_id => packet,
+                Ok(_) => continue,
+                Err(_) => return false,
+            };
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let spec = *decoded.spec();
+                    let samples = decoded.capacity() * spec.channels.count();
+                    if self.buffer.as_ref().is_none_or(|buffer| buffer.capacity() < samples) {
+                        self.buffer = Some(SampleBuffer::new(decoded.capacity() as u64, spec));
+                    }
+                    if let Some(buffer) = self.buffer.as_mut() {
+                        buffer.copy_interleaved_ref(decoded);
+                    }
+                    self.index = 0;
+                    return true;
+                }
+                // a damaged packet is skipped, as rodio does
+                Err(Error::DecodeError(_)) => continue,
+                Err(_) => return false,
+            }
+        }
+    }
+}
+
+impl Iterator for SeekableDecoder {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        loop {
+            if let Some(buffer) = self.buffer.as_ref() {
+                if self.index < buffer.len() {
+                    let sample = buffer.samples()[self.index];
+                    self.index += 1;
+                    if self.skip > 0 {
+                        self.skip -= 1;
+                        continue;
+                    }
+                    return Some(sample);
+                }
+            }
+            if !self.decode() {
+                return None;
+            }
+        }
+    }
+}
+
+impl Source for SeekableDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.duration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_wav::TestWav;
+
+    #[test]
+    fn seeks_to_the_sample() {
+        // two seconds of stereo at 8 kHz, each frame holding its number
+        let samples: Vec<i16> = (0..16000).flat_map(|frame| [frame as i16, -(frame as i16)]).collect();
+        let wav = TestWav::new(8000, 2, &samples);
+        let mut decoder = SeekableDecoder::open(File::open(&wav.path).unwrap(), "wav").unwrap();
+        assert_eq!((decoder.channels(), decoder.sample_rate()), (2, 8000));
+        assert_eq!(decoder.total_duration(), Some(Duration::from_secs(2)));
+
+        let reached = decoder.seek(1.25);
+        assert!((reached - 1.25).abs() < 1e-9, "{}", reached);
+        assert_eq!(decoder.next(), Some(10000));
+        assert_eq!(decoder.next(), Some(-10000));
+        assert_eq!(decoder.count(), (16000 - 10001) * 2);
+    }
+}
@@ -0,0 +1,233 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::config::PlayMode;
+use crate::settings;
+use crate::State;
+
+/// What the player picks up again on the next launch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// Where the playlist came from, see `source`; track and position only carry over
+    /// to a launch that plays the same.
+    #[serde(default)]
+    pub source: Vec<PathBuf>,
+    pub track: PathBuf,
+    pub index: usize,
+    /// Seconds into `track`.
+    pub position: f64,
+    pub queue: Vec<PathBuf>,
+    pub playing: bool,
+    #[serde(default)]
+    pub play_mode: PlayMode,
+    pub volume: f32,
+}
+
+/// Where the playback loop starts instead of the first track.
+#[derive(Debug, Clone)]
+pub struct Resume {
+    pub track: PathBuf,
+    /// Used when `track` is gone from the folder.
+    pub index: usize,
+    pub position: f64,
+}
+
+impl Session {
+    /// `None` before the first track started, which is nothing worth keeping.
+    pub fn of(state: &State) -> Option<Session> {
+        if state.file_path.as_os_str().is_empty() {
+            return None;
+        }
+        Some(Session {
+            source: source(state),
+            track: state.file_path.clone(),
+            index: state.track_index,
+            position: state.position,
+            queue: state.queue.clone(),
+            playing: state.play,
+            play_mode: state.play_mode,
+            volume: state.volume,
+        })
+    }
+
+    /// `None` without a saved session.
+    pub fn load(path: &Path) -> io::Result<Option<Session>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        serde_json::from_str(&text).map(Some).map_err(io::Error::other)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        settings::write_atomic(path, text.as_bytes())
+    }
+
+    /// Restores the queue, play mode and volume into `state`, and returns where
+    /// to resume playback when the playlist comes from the same folders as last time.
+    pub fn restore(self, state: &mut State) -> Option<Resume> {
+        state.queue = self.queue.into_iter().filter(|path| path.is_file()).collect();
+        state.play = self.playing;
+        state.play_mode = self.play_mode;
+        state.volume = self.volume.clamp(0.0, 1.0);
+        if source(state) != self.source {
+            return None;
+        }
+        Some(Resume {
+            track: self.track,
+            index: self.index,
+            position: self.position.max(0.0),
+        })
+    }
+}
+
+/// The folders the playlist is read from: the configured `music_dirs`, or the folder
+/// the player was started in when there are none.
+fn source(state: &State) -> Vec<PathBuf> {
+    let dirs = &state.config.playback.music_dirs;
+    if dirs.is_empty() {
+        std::env::current_dir().into_iter().collect()
+    } else {
+        dirs.clone()
+    }
+}
+
+pub fn path() -> PathBuf {
+    settings::data_dir().join("session.json")
+}
+
+/// Saves the session now, e.g. on the way out.
+pub fn save(state: &Arc<Mutex<State>>) {
+    let session = Session::of(&state.lock().unwrap());
+    if let Some(session) = session {
+        if let Err(error) = session.save(&path()) {
+            eprintln!("session: {}: {}", path().display(), error);
+        }
+    }
+}
+
+/// Saves the session every `settings::SESSION_SAVE_TIME` seconds when it changed,
+/// so a crash or a kill loses little.
+pub fn spawn(state: Arc<Mutex<State>>) {
+    thread::spawn(move || {
+        let mut last: Option<Session> = None;
+        let mut failing = false;
+        loop {
+            thread::sleep(Duration::from_secs_f64(settings::SESSION_SAVE_TIME));
+            let session = Session::of(&state.lock().unwrap());
+            let Some(session) = session else {
+                continue;
+            };
+            if last.as_ref() == Some(&session) {
+                continue;
+            }
+            match session.save(&path()) {
+                Ok(()) => failing = false,
+                Err(error) if !failing => {
+                    state.lock().unwrap().notifications.warning(format!("session: {}", error));
+                    failing = true;
+                }
+                Err(_) => {}
+            }
+            last = Some(session);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn state(music_dirs: &[&str]) -> State {
+        let mut config = Config::default();
+        config.playback.music_dirs = music_dirs.iter().map(PathBuf::from).collect();
+        State::new(config)
+    }
+
+    fn session(source: &[&str], queue: Vec<PathBuf>, volume: f32) -> Session {
+        Session {
+            source: source.iter().map(PathBuf::from).collect(),
+            track: PathBuf::from("/music/b.mp3"),
+            index: 1,
+            position: 42.5,
+            queue,
+            playing: false,
+            play_mode: PlayMode::RepeatOne,
+            volume,
+        }
+    }
+
+    fn temp(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("srmp-session-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn restores_into_the_same_playlist() {
+        let queued = temp("queued.mp3");
+        fs::write(&queued, b"").unwrap();
+        let gone = temp("gone.mp3");
+        let mut state = state(&["/music"]);
+        let resume = session(&["/music"], vec![gone, queued.clone()], 1.5).restore(&mut state);
+        let _ = fs::remove_file(&queued);
+
+        let resume = resume.unwrap();
+        assert_eq!((resume.track, resume.index, resume.position), (PathBuf::from("/music/b.mp3"), 1, 42.5));
+        assert_eq!(state.queue, vec![queued]);
+        assert_eq!(state.volume, 1.0);
+        assert!(!state.play);
+        assert_eq!(state.play_mode, PlayMode::RepeatOne);
+    }
+
+    #[test]
+    fn keeps_only_the_settings_for_another_playlist() {
+        let mut elsewhere = state(&["/elsewhere"]);
+        assert!(session(&["/music"], Vec::new(), -0.5).restore(&mut elsewhere).is_none());
+        assert_eq!(elsewhere.volume, 0.0);
+        assert_eq!(elsewhere.play_mode, PlayMode::RepeatOne);
+        // a session from before the source was saved
+        assert!(session(&[], Vec::new(), 0.5).restore(&mut state(&["/music"])).is_none());
+    }
+
+    #[test]
+    fn loads_missing_and_older_files() {
+        let path = temp("missing.json");
+        assert!(Session::load(&path).unwrap().is_none());
+
+        let path = temp("old.json");
+        fs::write(&path, r#"{"dir": "/music", "track": "/music/a.mp3", "index": 0, "position": 3.0, "queue": [], "playing": true, "volume": 0.5}"#).unwrap();
+        let loaded = Session::load(&path);
+        let _ = fs::remove_file(&path);
+        let loaded = loaded.unwrap().unwrap();
+        assert_eq!(loaded.play_mode, PlayMode::Repeat);
+        assert!(loaded.source.is_empty());
+
+        let path = temp("broken.json");
+        fs::write(&path, "{\"track\": ").unwrap();
+        let loaded = Session::load(&path);
+        let _ = fs::remove_file(&path);
+        assert!(loaded.is_err());
+    }
+
+    #[test]
+    fn saves_and_loads_the_same() {
+        let path = temp("dir").join("session.json");
+        let saved = session(&["/music", "/more music"], vec![PathBuf::from("/music/c.mp3")], 0.7);
+        saved.save(&path).unwrap();
+        let loaded = Session::load(&path);
+        let leftover = path.with_extension("json.tmp").exists();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        assert_eq!(loaded.unwrap(), Some(saved));
+        assert!(!leftover);
+    }
+}
@@ -1,31 +1,125 @@
-pub const WIDTH0: i32 = 512;
-pub const HEIGHT0: i32 = 512;
-pub const WIDTH: u32 = 512;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+
+pub const APP_NAME: &str = "small-rust-music-player";
+// Reference height the layout is designed for, scaled to the framebuffer
 pub const HEIGHT: u32 = 512;
-pub const FT_DESIRED: f64 = 1.0/120.0;
-pub const SAMPLING_TIME: f64 = 1.0/40.0;
+pub const MIN_UI_SCALE: f32 = 0.5;
+pub const UI_SCALE_STEP: f32 = 1.0/16.0;
 pub const MAX_QUADS_OVERLAY: usize = 1000;
 pub const MAX_VERTICES_OVERLAY: usize = MAX_QUADS_OVERLAY*4;
 pub const MAX_INDICES_OVERLAY: usize = MAX_QUADS_OVERLAY*6;
+// Samples per tap; not in the config, as user shaders rely on the 512 wide textures it gives
 pub const SAMPLES: usize = 1024;
 pub const _AVERAGE_FREQ: usize = 16;
-pub const BINS: usize = 32;
-pub const BIN_SAMPLES: usize = SAMPLES/BINS;
 pub const AVERAGE_TIME: usize = 1;
-pub const MAX_VERTICES_VISUALS: usize = BINS*4;
-pub const MAX_INDICES_VISUALS: usize = BINS*6;
+pub const PULSE_DECAY: f64 = 6.0;
+// Most bars the built-in visualizer can be configured with
+pub const MAX_BARS: usize = 128;
+pub const MAX_VERTICES_VISUALS: usize = MAX_BARS*4;
+pub const MAX_INDICES_VISUALS: usize = MAX_BARS*6;
 pub const MAX_QUADS_GUI: usize = 1000;
 pub const MAX_VERTICES_GUI: usize = MAX_QUADS_GUI*4;
 pub const MAX_INDICES_GUI: usize = MAX_QUADS_GUI*6;
-pub const _CLR1: (f32, f32, f32, f32) = (0.5294118, 0.8078431, 0.9215686, 1.0000000); // sky blue
-pub const CLR2: (f32, f32, f32, f32) = (0.14117647, 0.07843137, 0.13333333, 1.0); // dark purple
-pub const _CLR3: (f32, f32, f32, f32) = (0.8, 0.0, 0.2, 1.0); // nice red
-pub const CLR4: (f32, f32, f32, f32) = (0.1568627, 0.1568627, 0.1568627, 1.0); // almost black
-pub const _CLR5: (f32, f32, f32, f32) = (0.9960784, 0.7607843, 0.5568627, 1.0); // pale yellow
-pub const CLR6: (f32, f32, f32, f32) = (0.1843137, 0.2666667, 0.4627451, 1.0000000); // gray-blue
-pub const _CLR7: (f32, f32, f32, f32) = (0.0, 0.2745098, 0.6666667, 1.0000000); // blue
-pub const CLR8: (f32, f32, f32, f32) = (0.85, 0.85, 0.85, 1.0000000); // almost white
-pub const _CLR9: (f32, f32, f32, f32) = (0.5105882, 0.5600000, 0.6776471, 1.0000000); // pale blue
+pub const WAVEFORM_COLUMNS: usize = 128;
+pub const WAVEFORM_BLOCK: usize = 1024;
+pub const MAX_QUADS_WAVEFORM: usize = WAVEFORM_COLUMNS*2+1;
+pub const MAX_VERTICES_WAVEFORM: usize = MAX_QUADS_WAVEFORM*4;
+pub const MAX_INDICES_WAVEFORM: usize = MAX_QUADS_WAVEFORM*6;
+pub const THEME_POLL_TIME: f64 = 1.0;
+pub const CONFIG_POLL_TIME: f64 = 1.0;
+pub const MAX_ERROR_LINES: usize = 4;
+pub const GUI_TEXT_WIDTH: f32 = 400.0;
+pub const MARQUEE_SPEED: f32 = 30.0;
+pub const MARQUEE_PAUSE: f32 = 1.5;
+pub const RESTART_TIME: f64 = 3.0;
+pub const TOAST_INFO_TIME: f64 = 3.0;
+pub const TOAST_WARNING_TIME: f64 = 6.0;
+pub const TOAST_ERROR_TIME: f64 = 10.0;
+pub const TOAST_FADE: f64 = 0.75;
+pub const MAX_TOASTS: usize = 4;
+pub const CAPTION_TIME: f64 = 4.0;
+pub const CAPTION_FADE: f64 = 1.0;
+pub const MPRIS_POLL_TIME: f64 = 0.25;
+// A position change this far (seconds) off the playback clock counts as a seek
+pub const SEEK_JUMP: f64 = 1.0;
+pub const CONTROL_POLL_TIME: f64 = 0.25;
+// A subscriber that does not take an event within this many seconds is dropped
+pub const CONTROL_WRITE_TIMEOUT: f64 = 1.0;
+// Where `--http` serves the web remote; other addresses are given as `--http=ADDRESS`
+pub const HTTP_ADDRESS: &str = "127.0.0.1:8390";
+pub const HTTP_PUSH_TIME: f64 = 0.5;
+// A track counts as played after half its length or this many seconds, whichever comes first
+pub const SCROBBLE_TIME: f64 = 240.0;
+pub const SESSION_SAVE_TIME: f64 = 10.0;
+pub const NOW_PLAYING_POLL_TIME: f64 = 0.25;
+pub const NOW_PLAYING_TEMPLATE: &str = "{artist} - {title}";
+pub const NOTIFY_POLL_TIME: f64 = 0.25;
+// A track is announced once it has been playing this long, so skipping through several announces only the last
+pub const NOTIFY_SETTLE_TIME: f64 = 1.0;
+pub const CHORD_TIMEOUT: f64 = 1.0;
+pub const PLAYLIST_ROWS: usize = 10;
+// Tracks in each list of the stats screen and of `stats` on the command line
+pub const STATS_ROWS: usize = 10;
+pub const MAX_QUADS_SLIDERS: usize = 3*16;
+pub const MAX_VERTICES_SLIDERS: usize = MAX_QUADS_SLIDERS*4;
+pub const MAX_INDICES_SLIDERS: usize = MAX_QUADS_SLIDERS*6;
+
+/// Per-user configuration directory: `$XDG_CONFIG_HOME/small-rust-music-player`,
+/// falling back to `~/.config`, or `%APPDATA%` on Windows.
+pub fn config_dir() -> PathBuf {
+    let base = match std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(std::env::var_os("APPDATA").unwrap_or_default()),
+        None => PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".config"),
+    };
+    base.join(APP_NAME)
+}
+
+/// Per-user data directory: `$XDG_DATA_HOME/small-rust-music-player`,
+/// falling back to `~/.local/share`, or `%APPDATA%` on Windows.
+pub fn data_dir() -> PathBuf {
+    let base = match std::env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(std::env::var_os("APPDATA").unwrap_or_default()),
+        None => PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".local/share"),
+    };
+    base.join(APP_NAME)
+}
+
+/// Remote control socket: `$XDG_RUNTIME_DIR/small-rust-music-player.sock`,
+/// falling back to a per-user name in the temporary directory.
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join(format!("{}.sock", APP_NAME)),
+        None => {
+            let user = std::env::var("USER").unwrap_or_default();
+            std::env::temp_dir().join(format!("{}-{}.sock", APP_NAME, user))
+        }
+    }
+}
+
+/// Where `--now-playing` writes by default: `$XDG_RUNTIME_DIR/small-rust-music-player`,
+/// falling back to a per-user name in the temporary directory.
+pub fn runtime_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join(APP_NAME),
+        None => {
+            let user = std::env::var("USER").unwrap_or_default();
+            std::env::temp_dir().join(format!("{}-{}", APP_NAME, user))
+        }
+    }
+}
+
+/// Writes `contents` next to `path` and renames it into place,
+/// so readers see either the old file or the new one, never a partial write.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    std::fs::write(&temporary, contents)?;
+    std::fs::rename(&temporary, path)
+}
 
 pub struct Settings {
     pub screen_width: i32,
@@ -34,13 +128,15 @@ pub struct Settings {
     pub screen_width_f: f32,
     pub screen_height_f: f32,
     pub screen_aspect: f32,
+    pub dpi_scale: f32,
+    pub ui_scale: f32,
 }
 
 impl Settings {
-    pub fn init() -> Settings {
-        let screen_width = WIDTH0;
-        let screen_height = HEIGHT0;
-        let full_screen = false;
+    pub fn init(display: &config::Display) -> Settings {
+        let screen_width = display.width;
+        let screen_height = display.height;
+        let full_screen = display.fullscreen;
         let screen_width_f = screen_width as f32;
         let screen_height_f = screen_height as f32;
         let screen_aspect = screen_width_f/screen_height_f;
@@ -51,14 +147,25 @@ impl Settings {
             screen_width_f,
             screen_height_f,
             screen_aspect,
+            dpi_scale: 1.0,
+            ui_scale: 1.0,
         }
     }
 
-    pub fn screen_change(&mut self, screen_width: f32, screen_height: f32) {
+    /// Takes the framebuffer size in pixels. The layout scales with the smaller side of the window
+    /// in logical pixels, but never below `MIN_UI_SCALE`, times the DPI scale; the result is snapped
+    /// to `UI_SCALE_STEP` so resizing does not re-rasterize the glyphs on every pixel.
+    pub fn screen_change(&mut self, screen_width: f32, screen_height: f32, dpi_scale: f32) {
+        let screen_width = screen_width.max(1.0);
+        let screen_height = screen_height.max(1.0);
         self.screen_width = screen_width as i32;
         self.screen_height = screen_height as i32;
         self.screen_width_f = screen_width;
         self.screen_height_f = screen_height;
         self.screen_aspect = screen_width/screen_height;
+        self.dpi_scale = dpi_scale;
+        let logical = screen_width.min(screen_height) / dpi_scale / HEIGHT as f32;
+        let ui_scale = logical.max(MIN_UI_SCALE) * dpi_scale;
+        self.ui_scale = ((ui_scale / UI_SCALE_STEP).round() * UI_SCALE_STEP).max(UI_SCALE_STEP);
     }
 }
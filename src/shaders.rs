@@ -1,4 +1,8 @@
 use miniquad::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::settings;
 
 pub const VERTEX_OVERLAY: &str = r#"#version 330 core
 in vec3 pos;
@@ -6,14 +10,17 @@ in vec2 uv;
 in float act;
 
 out vec2 texcoord;
+out float alpha;
 
 void main() {
     gl_Position = vec4((pos.x-0.5)*2.0, (0.5-pos.y)*2.0, 0.0, 1.0);
     texcoord = uv;
+    alpha = act;
 }"#;
 
 pub const FRAGMENT_OVERLAY: &str = r#"#version 330 core
 in vec2 texcoord;
+in float alpha;
 
 out vec4 FragColor;
 
@@ -25,7 +32,7 @@ vec4 col;
 void main() {
     col = texture(tex, texcoord);
 
-    FragColor = vec4(fontcolor.xyz,col.w);
+    FragColor = vec4(fontcolor.xyz,col.w*fontcolor.w*alpha);
 }"#;
 
 pub const VERTEX_GUI: &str = r#"#version 330 core
@@ -35,22 +42,27 @@ in float act;
 
 uniform vec4 fontcolor;
 uniform vec4 actcolor;
+uniform vec4 panelcolor;
 
 out vec2 texcoord;
 out vec4 cols;
 out vec2 posxy;
+out float panel;
 
 void main() {
+    float a = max(act, 0.0);
     posxy = vec2((pos.x-0.5)*2.0, (0.5-pos.y)*2.0);
-    gl_Position = vec4(posxy + act*vec2(0.005,-0.005), 0.0, 1.0);
+    gl_Position = vec4(posxy + a*vec2(0.005,-0.005), 0.0, 1.0);
     texcoord = uv;
-    cols = actcolor*act + fontcolor*(1.0-act);
+    panel = act < 0.0 ? 1.0 : 0.0;
+    cols = panelcolor*panel + (actcolor*a + fontcolor*(1.0-a))*(1.0-panel);
 }"#;
 
 pub const FRAGMENT_GUI: &str = r#"#version 330 core
 in vec2 texcoord;
 in vec4 cols;
 in vec2 posxy;
+in float panel;
 
 out vec4 FragColor;
 
@@ -60,7 +72,10 @@ vec4 col;
 
 void main() {
     col = texture(tex, texcoord);
-    FragColor = vec4(col.xyz+(1-col.xyz)*cols.xyz,col.w);
+    // the panel box of the font sheet is 80% gray with darker edges
+    vec3 panelcol = min(col.xyz/0.8, 1.0)*cols.xyz;
+    vec3 textcol = col.xyz+(1-col.xyz)*cols.xyz;
+    FragColor = vec4(mix(textcol, panelcol, panel),col.w);
     
 }"#;
 
@@ -81,10 +96,40 @@ in vec2 texcoord;
 
 out vec4 FragColor;
 
+uniform vec4 lowcolor;
+uniform vec4 highcolor;
+
+void main() {
+    vec4 col = mix(lowcolor, highcolor, texcoord.x);
+    FragColor = vec4(col.xyz, col.w*texcoord.x);
+}"#;
+
+pub const VERTEX_WAVEFORM: &str = r#"#version 330 core
+in vec3 pos;
+in vec2 uv;
+in float act;
+
+out vec2 texcoord;
+out float played;
+
+void main() {
+    gl_Position = vec4((pos.x-0.5)*2.0, (0.5-pos.y)*2.0, 0.0, 1.0);
+    texcoord = uv;
+    played = act;
+}"#;
+
+pub const FRAGMENT_WAVEFORM: &str = r#"#version 330 core
+in vec2 texcoord;
+in float played;
+
+out vec4 FragColor;
+
 uniform vec4 fontcolor;
+uniform vec4 actcolor;
 
 void main() {
-    FragColor = fontcolor*vec4(texcoord.x, 0.5*texcoord.x, 1.0-texcoord.x, texcoord.x);
+    vec4 col = mix(fontcolor, actcolor, played);
+    FragColor = vec4(col.xyz, col.w*(0.4+0.6*texcoord.x));
 }"#;
 
 pub const VERTEX_SCREEN: &str = r#"#version 330 core
@@ -110,6 +155,63 @@ void main() {
     FragColor = texture(tex, texcoord);
 }"#;
 
+pub const VERTEX_CUSTOM: &str = r#"#version 330 core
+in vec3 pos;
+in vec2 uv;
+in float act;
+
+void main() {
+    gl_Position = vec4(pos.xy, 0.0, 1.0);
+}"#;
+
+// User visualizers are Shadertoy-style fragment shaders defining
+// `void mainImage(out vec4 fragColor, in vec2 fragCoord)`
+const HEADER_CUSTOM: &str = r#"#version 330 core
+out vec4 FragColor;
+
+uniform float iTime;
+uniform vec3 iResolution;
+uniform float iBeat;
+uniform float iProgress;
+uniform sampler2D iSpectrum;
+uniform sampler2D iWaveform;
+
+#line 1
+"#;
+
+const FOOTER_CUSTOM: &str = r#"
+void main() {
+    mainImage(FragColor, gl_FragCoord.xy);
+}"#;
+
+pub fn fragment_custom(source: &str) -> String {
+    format!("{}{}{}", HEADER_CUSTOM, source, FOOTER_CUSTOM)
+}
+
+pub fn custom_dir() -> PathBuf {
+    settings::config_dir().join("shaders")
+}
+
+/// Fragment shader files (`*.frag` or `*.glsl`) in the user shader folder.
+pub fn scan_custom() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = match fs::read_dir(custom_dir()) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|x| x == "frag" || x == "glsl"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    files.sort();
+    files
+}
+
+pub fn load_custom(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path)
+        .map(|source| fragment_custom(&source))
+        .map_err(|e| e.to_string())
+}
+
 pub fn meta_overlay() -> ShaderMeta {
     ShaderMeta {
         images: vec!["tex".to_string()],
@@ -126,7 +228,8 @@ pub fn meta_visuals() -> ShaderMeta {
         images: vec![],
         uniforms: UniformBlockLayout {
             uniforms: vec![
-                UniformDesc::new("fontcolor", UniformType::Float4),
+                UniformDesc::new("lowcolor", UniformType::Float4),
+                UniformDesc::new("highcolor", UniformType::Float4),
             ],
         },
     }
@@ -135,6 +238,19 @@ pub fn meta_visuals() -> ShaderMeta {
 pub fn meta_gui() -> ShaderMeta {
     ShaderMeta {
         images: vec!["tex".to_string()],
+        uniforms: UniformBlockLayout {
+            uniforms: vec![
+                UniformDesc::new("fontcolor", UniformType::Float4),
+                UniformDesc::new("actcolor", UniformType::Float4),
+                UniformDesc::new("panelcolor", UniformType::Float4),
+            ],
+        },
+    }
+}
+
+pub fn meta_waveform() -> ShaderMeta {
+    ShaderMeta {
+        images: vec![],
         uniforms: UniformBlockLayout {
             uniforms: vec![
                 UniformDesc::new("fontcolor", UniformType::Float4),
@@ -154,6 +270,20 @@ pub fn meta_screen() -> ShaderMeta {
     }
 }
 
+pub fn meta_custom() -> ShaderMeta {
+    ShaderMeta {
+        images: vec!["iSpectrum".to_string(), "iWaveform".to_string()],
+        uniforms: UniformBlockLayout {
+            uniforms: vec![
+                UniformDesc::new("iTime", UniformType::Float1),
+                UniformDesc::new("iResolution", UniformType::Float3),
+                UniformDesc::new("iBeat", UniformType::Float1),
+                UniformDesc::new("iProgress", UniformType::Float1),
+            ],
+        },
+    }
+}
+
 #[repr(C)]
 pub struct UniformsOverlay {
     pub fontcolor: (f32, f32, f32, f32),
@@ -163,13 +293,29 @@ pub struct UniformsOverlay {
 pub struct UniformsGUI {
     pub fontcolor: (f32, f32, f32, f32),
     pub actcolor: (f32, f32, f32, f32),
+    pub panelcolor: (f32, f32, f32, f32),
 }
 
 #[repr(C)]
 pub struct UniformsVisuals {
+    pub lowcolor: (f32, f32, f32, f32),
+    pub highcolor: (f32, f32, f32, f32),
+}
+
+#[repr(C)]
+pub struct UniformsWaveform {
     pub fontcolor: (f32, f32, f32, f32),
+    pub actcolor: (f32, f32, f32, f32),
 }
 
 #[repr(C)]
 pub struct UniformsScreen {
 }
+
+#[repr(C)]
+pub struct UniformsCustom {
+    pub time: f32,
+    pub resolution: (f32, f32, f32),
+    pub beat: f32,
+    pub progress: f32,
+}
@@ -0,0 +1,81 @@
+use std::f32::consts::PI;
+
+use crate::settings;
+
+pub const SPECTRUM_BINS: usize = settings::SAMPLES / 2;
+const MIN_DB: f32 = -90.0;
+const MAX_DB: f32 = -10.0;
+const SMOOTHING: f32 = 0.8;
+
+/// Smoothed magnitude spectrum of the tapped samples, scaled to 0..1 like the
+/// Web Audio analyser Shadertoy uses.
+pub struct Spectrum {
+    pub bins: [f32; SPECTRUM_BINS],
+    window: [f32; settings::SAMPLES],
+}
+
+impl Spectrum {
+    pub fn init() -> Spectrum {
+        let mut window = [0.0; settings::SAMPLES];
+        for (n, w) in window.iter_mut().enumerate() {
+            *w = 0.5 - 0.5 * (2.0 * PI * n as f32 / settings::SAMPLES as f32).cos();
+        }
+        Spectrum {
+            bins: [0.0; SPECTRUM_BINS],
+            window,
+        }
+    }
+
+    pub fn update(&mut self, samples: &[i16; settings::SAMPLES]) {
+        let mut re = [0.0f32; settings::SAMPLES];
+        let mut im = [0.0f32; settings::SAMPLES];
+        for n in 0..settings::SAMPLES {
+            re[n] = samples[n] as f32 / 32768.0 * self.window[n];
+        }
+        fft(&mut re, &mut im);
+
+        for k in 0..SPECTRUM_BINS {
+            let magnitude = (re[k] * re[k] + im[k] * im[k]).sqrt() * 2.0 / settings::SAMPLES as f32;
+            let db = 20.0 * magnitude.max(1e-9).log10();
+            let level = ((db - MIN_DB) / (MAX_DB - MIN_DB)).clamp(0.0, 1.0);
+            self.bins[k] = SMOOTHING * self.bins[k] + (1.0 - SMOOTHING) * level;
+        }
+    }
+}
+
+/// In-place iterative radix-2 FFT, `re.len()` must be a power of two.
+pub fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + len / 2;
+                let tre = re[b] * cos - im[b] * sin;
+                let tim = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tre;
+                im[b] = im[a] - tim;
+                re[a] += tre;
+                im[a] += tim;
+            }
+        }
+        len <<= 1;
+    }
+}
@@ -1,13 +1,25 @@
-use image::{self, EncodableLayout, ImageBuffer, Rgba};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use miniquad::*;
 
-use crate::assets;
+use crate::command::Command;
+#[cfg(unix)]
+use crate::control;
+use crate::config;
+use crate::history;
+use crate::session;
+use crate::font;
 use crate::mesh;
+use crate::search;
 use crate::settings;
 use crate::shaders;
+use crate::spectrum;
 use crate::text;
+use crate::theme;
+use crate::widgets;
 use crate::input::{TimeState, InputState};
+use crate::keymap::{self, Action};
 
 pub struct Stage {
     ctx: Box<dyn RenderingBackend>,
@@ -15,16 +27,50 @@ pub struct Stage {
     settings: settings::Settings,
     overlay: text::Overlay,
     gui: text::GUI,
+    widgets: widgets::Widgets,
     mesh: Vec<mesh::Mesh>,
     render_pass: RenderPass,
     pipeline: Vec<Pipeline>,
     bindings: Vec<Bindings>,
     state: Arc<Mutex<crate::State>>,
+    config: config::Config,
+    config_watch: config::Watch,
+    themes: theme::Themes,
+    fonts: font::Fonts,
+    font_texture: TextureId,
+    font_paths: (Option<PathBuf>, Vec<PathBuf>),
+    spectrum: spectrum::Spectrum,
+    custom_list: Vec<PathBuf>,
+    custom_index: usize,
+    custom_shader: Option<ShaderId>,
+    custom_pipeline: Option<Pipeline>,
+    custom_error: Vec<String>,
+    start_time: f64,
 
     time_state: TimeState,
     input_state: InputState,
     counter: usize,
     other_counter: f64,
+    position: f64,
+    reported: f64,
+    progress: f32,
+    pulse: f32,
+    remaining: bool,
+    playlist_view: bool,
+    help_view: bool,
+    stats_view: bool,
+    stats_rows: Vec<String>,
+    // first row shown of the help or stats panel
+    panel_scroll: usize,
+    presentation: bool,
+    caption_path: PathBuf,
+    caption_time: f64,
+    selected: usize,
+    scroll: usize,
+    query: String,
+    results: Vec<usize>,
+    // the query, playlist and number of tags `results` were filtered from
+    searched: Option<(String, Vec<PathBuf>, usize)>,
     visuals: [[i16; settings::SAMPLES]; settings::AVERAGE_TIME],
 }
 
@@ -32,27 +78,61 @@ impl Stage {
     pub fn new(state: Arc<Mutex<crate::State>>) -> Stage {
         let mut ctx: Box<dyn RenderingBackend> = window::new_rendering_backend();
 
-        let settings = settings::Settings::init();
-        let ass = assets::Ass::load();
+        let config = state.lock().unwrap().config.clone();
+        let mut settings = settings::Settings::init(&config.display);
+        let (width, height) = window::screen_size();
+        settings.screen_change(width, height, window::dpi_scale());
+        let (mut fonts, _) = font::Fonts::load(None, &[]);
 
-        let overlay = text::Overlay::new_from(vec!["Text default"]);
-        let gui = text::GUI::new_from(vec!["Text default"], settings.screen_width_f, settings.screen_height_f);
+        let (keymap, keymap_error) = keymap::Keymap::load();
+        if let Some(error) = keymap_error {
+            state.lock().unwrap().notifications.error(format!("keys: {}", error));
+        }
+        let (themes, theme_error) = theme::Themes::init(&config.display.theme, config.colors.clone());
+        if let Some(error) = theme_error {
+            state.lock().unwrap().notifications.error(format!("theme: {}", error));
+        }
+        let scale = themes.theme.font_scale * settings.ui_scale;
+
+        let overlay = text::Overlay::new_from(vec!["Text default"], settings.screen_width_f, scale, &mut fonts);
+        let gui = text::GUI::new_from(vec!["Text default"], settings.screen_width_f, settings.screen_height_f, scale, &mut fonts);
 
         let mesh_overlay = mesh::Mesh::new_overlay(
             &overlay,
+            &mut fonts,
             1.0 / settings.screen_width_f,
             1.0 / settings.screen_height_f,
         );
+        let controls = widgets::Widgets::init(state.lock().unwrap().volume);
+
         let mesh_gui = mesh::Mesh::new_gui(
             &gui,
+            &controls,
+            &mut fonts,
             1.0 / settings.screen_width_f,
             1.0 / settings.screen_height_f,
         );
 
-        let mesh_visuals = mesh::Mesh::new_visuals(&[0; settings::SAMPLES]);
+        let mesh_visuals = mesh::Mesh::new_visuals(&[0; settings::SAMPLES], 1.0, config.display.bars);
 
         let mesh_screen = mesh::Mesh::new_screen(1.0);
 
+        let mesh_custom = mesh::Mesh::new_screen(1.0);
+
+        let mesh_sliders = mesh::Mesh::new_sliders(
+            &controls.sliders,
+            1.0 / settings.screen_width_f,
+            1.0 / settings.screen_height_f,
+        );
+
+        let mesh_waveform = mesh::Mesh::new_waveform(
+            None,
+            0.0,
+            gui.waveform_rect(),
+            1.0 / settings.screen_width_f,
+            1.0 / settings.screen_height_f,
+        );
+
         let vertex_buffer_overlay = ctx.new_buffer(
             BufferType::VertexBuffer,
             BufferUsage::Stream,
@@ -71,6 +151,24 @@ impl Stage {
             BufferSource::empty::<mesh::Vertex>(settings::MAX_VERTICES_GUI),
         );
 
+        let vertex_buffer_waveform = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Stream,
+            BufferSource::empty::<mesh::Vertex>(settings::MAX_VERTICES_WAVEFORM),
+        );
+
+        let vertex_buffer_sliders = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Stream,
+            BufferSource::empty::<mesh::Vertex>(settings::MAX_VERTICES_SLIDERS),
+        );
+
+        let vertex_buffer_custom = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Stream,
+            BufferSource::slice(&mesh_custom.vertices),
+        );
+
         let vertex_buffer_screen = ctx.new_buffer(
             BufferType::VertexBuffer,
             BufferUsage::Immutable,
@@ -95,15 +193,30 @@ impl Stage {
             BufferSource::empty::<i16>(settings::MAX_INDICES_GUI),
         );
 
+        let index_buffer_waveform = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Stream,
+            BufferSource::empty::<i16>(settings::MAX_INDICES_WAVEFORM),
+        );
+
+        let index_buffer_sliders = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Stream,
+            BufferSource::empty::<i16>(settings::MAX_INDICES_SLIDERS),
+        );
+
+        let index_buffer_custom = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Stream,
+            BufferSource::slice(&mesh_custom.indices),
+        );
+
         let index_buffer_screen = ctx.new_buffer(
             BufferType::IndexBuffer,
             BufferUsage::Stream,
             BufferSource::slice(&mesh_screen.indices),
         );
 
-        let pixels: ImageBuffer<Rgba<u8>, Vec<u8>> = ass.font;
-        let dims = pixels.dimensions();
-
         let mut t_params = TextureParams {
             kind: TextureKind::Texture2D,
             format: TextureFormat::RGBA8,
@@ -112,12 +225,13 @@ impl Stage {
             mag_filter: FilterMode::Nearest,
             mipmap_filter: MipmapFilterMode::None,
             sample_count: 1,
-            width: dims.0,
-            height: dims.1,
+            width: fonts.size(),
+            height: fonts.size(),
             allocate_mipmaps: false,
         };
 
-        let texture_overlay = ctx.new_texture_from_data_and_format(pixels.as_bytes(), t_params);
+        let texture_overlay = ctx.new_texture_from_data_and_format(fonts.atlas.as_raw(), t_params);
+        fonts.dirty = false;
 
         t_params = TextureParams {
             kind: TextureKind::Texture2D,
@@ -127,13 +241,30 @@ impl Stage {
             mag_filter: FilterMode::Nearest,
             mipmap_filter: MipmapFilterMode::None,
             sample_count: 1,
-            width: settings::WIDTH,
-            height: settings::HEIGHT,
+            width: settings.screen_width as u32,
+            height: settings.screen_height as u32,
             allocate_mipmaps: false,
         };
 
         let texture = ctx.new_render_texture(t_params);
 
+        // One row textures fed to user shaders, like the audio input of Shadertoy
+        t_params = TextureParams {
+            kind: TextureKind::Texture2D,
+            format: TextureFormat::RGBA8,
+            wrap: TextureWrap::Clamp,
+            min_filter: FilterMode::Linear,
+            mag_filter: FilterMode::Linear,
+            mipmap_filter: MipmapFilterMode::None,
+            sample_count: 1,
+            width: spectrum::SPECTRUM_BINS as u32,
+            height: 1,
+            allocate_mipmaps: false,
+        };
+
+        let texture_spectrum = ctx.new_texture_from_data_and_format(&[0; spectrum::SPECTRUM_BINS * 4], t_params);
+        let texture_waveform = ctx.new_texture_from_data_and_format(&[0; spectrum::SPECTRUM_BINS * 4], t_params);
+
         let bindings_overlay = Bindings {
             vertex_buffers: vec![vertex_buffer_overlay],
             index_buffer: index_buffer_overlay,
@@ -152,6 +283,24 @@ impl Stage {
             images: vec![texture_overlay],
         };
 
+        let bindings_waveform = Bindings {
+            vertex_buffers: vec![vertex_buffer_waveform],
+            index_buffer: index_buffer_waveform,
+            images: vec![],
+        };
+
+        let bindings_sliders = Bindings {
+            vertex_buffers: vec![vertex_buffer_sliders],
+            index_buffer: index_buffer_sliders,
+            images: vec![],
+        };
+
+        let bindings_custom = Bindings {
+            vertex_buffers: vec![vertex_buffer_custom],
+            index_buffer: index_buffer_custom,
+            images: vec![texture_spectrum, texture_waveform],
+        };
+
         let bindings_screen = Bindings {
             vertex_buffers: vec![vertex_buffer_screen],
             index_buffer: index_buffer_screen,
@@ -188,6 +337,16 @@ impl Stage {
             )
             .unwrap();
 
+        let shader_waveform = ctx
+            .new_shader(
+                miniquad::ShaderSource::Glsl {
+                    vertex: shaders::VERTEX_WAVEFORM,
+                    fragment: shaders::FRAGMENT_WAVEFORM,
+                },
+                shaders::meta_waveform(),
+            )
+            .unwrap();
+
         let shader_screen = ctx
             .new_shader(
                 miniquad::ShaderSource::Glsl {
@@ -250,6 +409,17 @@ impl Stage {
             p_params,
         );
 
+        let pipeline_waveform = ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &[
+                VertexAttribute::new("pos", VertexFormat::Float3),
+                VertexAttribute::new("uv", VertexFormat::Float2),
+                VertexAttribute::new("act", VertexFormat::Float1),
+            ],
+            shader_waveform,
+            p_params,
+        );
+
         p_params = PipelineParams {
             cull_face: CullFace::Nothing,
             front_face_order: FrontFaceOrder::CounterClockwise,
@@ -280,48 +450,547 @@ impl Stage {
             p_params,
         );
 
-        let gui = text::GUI::new_from(vec!["Text default"], settings.screen_width_f, settings.screen_height_f);
-
         let render_pass = ctx.new_render_pass(texture, None);
 
-        Stage {
+        let mut stage = Stage {
             ctx,
 
+            overlay,
             settings,
-            overlay: text::Overlay::new_from(vec!["Text default"]),
             gui,
-            pipeline: vec![pipeline_overlay, pipeline_gui, pipeline_visuals, pipeline_screen],
-            bindings: vec![bindings_overlay, bindings_gui, bindings_visuals, bindings_screen],
-            mesh: vec![mesh_overlay, mesh_gui, mesh_visuals, mesh_screen],
+            widgets: controls,
+            pipeline: vec![pipeline_overlay, pipeline_gui, pipeline_visuals, pipeline_screen, pipeline_waveform],
+            bindings: vec![bindings_overlay, bindings_gui, bindings_visuals, bindings_screen, bindings_waveform, bindings_sliders, bindings_custom],
+            mesh: vec![mesh_overlay, mesh_gui, mesh_visuals, mesh_screen, mesh_waveform, mesh_sliders, mesh_custom],
             render_pass,
             state,
+            config,
+            config_watch: config::Watch::init(),
+            themes,
+            fonts,
+            font_texture: texture_overlay,
+            font_paths: (None, Vec::new()),
+            spectrum: spectrum::Spectrum::init(),
+            custom_list: shaders::scan_custom(),
+            custom_index: 0,
+            custom_shader: None,
+            custom_pipeline: None,
+            custom_error: Vec::new(),
+            start_time: date::now(),
 
             time_state: TimeState::init(),
-            input_state: InputState::init(),
+            input_state: InputState::init(keymap),
             counter: 0,
             other_counter: 0.0,
+            position: 0.0,
+            reported: 0.0,
+            progress: 0.0,
+            pulse: 0.0,
+            remaining: false,
+            playlist_view: false,
+            help_view: false,
+            stats_view: false,
+            stats_rows: Vec::new(),
+            panel_scroll: 0,
+            presentation: false,
+            caption_path: PathBuf::new(),
+            caption_time: f64::NEG_INFINITY,
+            selected: 0,
+            scroll: 0,
+            query: String::new(),
+            results: Vec::new(),
+            searched: None,
             visuals: [[0; settings::SAMPLES]; settings::AVERAGE_TIME],
+        };
+        stage.apply_font();
+        stage
+    }
+
+    /// Takes in a changed `config.toml`; what is only read at startup waits for a restart.
+    fn reload_config(&mut self, result: Result<config::Config, String>) {
+        let config = match result {
+            Ok(config) => config,
+            Err(error) => {
+                self.state.lock().unwrap().notifications.error(format!("config: {}", error));
+                return;
+            }
+        };
+        let restart = config.restart_changes(&self.config);
+        let mut s_main = self.state.lock().unwrap();
+        if config.playback.play_mode != self.config.playback.play_mode {
+            s_main.play_mode = config.playback.play_mode;
+        }
+        s_main.config = config.clone();
+        s_main.notifications.info("config reloaded".to_string());
+        if !restart.is_empty() {
+            s_main.notifications.info(format!("config: {} apply after a restart", restart.join(", ")));
+        }
+        drop(s_main);
+        let colors_changed = self.themes.set_builtin(config.colors.clone());
+        self.config = config;
+        if colors_changed {
+            self.apply_font();
         }
     }
 
-    fn show_gui(&mut self) {
+    /// Replaces the offscreen render target with one the size of the framebuffer.
+    fn resize_target(&mut self) {
+        self.ctx.delete_render_pass(self.render_pass);
+        let texture = self.ctx.new_render_texture(TextureParams {
+            kind: TextureKind::Texture2D,
+            format: TextureFormat::RGBA8,
+            wrap: TextureWrap::Clamp,
+            min_filter: FilterMode::Linear,
+            mag_filter: FilterMode::Nearest,
+            mipmap_filter: MipmapFilterMode::None,
+            sample_count: 1,
+            width: self.settings.screen_width as u32,
+            height: self.settings.screen_height as u32,
+            allocate_mipmaps: false,
+        });
+        self.render_pass = self.ctx.new_render_pass(texture, None);
+        self.bindings[3].images[0] = texture;
+    }
+
+    /// Rebuilds the font chain and its atlas when the theme selects other fonts.
+    fn apply_font(&mut self) {
+        let font_paths = (self.themes.theme.font_path(), self.themes.theme.fallback_paths());
+        if font_paths == self.font_paths {
+            return;
+        }
+        let (fonts, errors) = font::Fonts::load(font_paths.0.as_deref(), &font_paths.1);
+        if !errors.is_empty() {
+            self.state.lock().unwrap().notifications.error(format!("font: {}", errors.join("; ")));
+        }
+        self.fonts = fonts;
+        self.font_paths = font_paths;
+        self.upload_font();
+    }
+
+    /// Copies the atlas to the GPU after new glyphs were rasterized.
+    fn upload_font(&mut self) {
+        if self.fonts.dirty {
+            let size = self.fonts.size();
+            if self.ctx.texture_size(self.font_texture) == (size, size) {
+                self.ctx.texture_update(self.font_texture, self.fonts.atlas.as_raw());
+            } else {
+                self.ctx.texture_resize(self.font_texture, size, size, Some(self.fonts.atlas.as_raw()));
+            }
+            self.fonts.dirty = false;
+        }
+    }
+
+    /// Runs a transport command from the keyboard or the buttons.
+    fn transport(&mut self, action: widgets::Transport) {
+        let command = match action {
+            widgets::Transport::PlayPause => Command::PlayPause,
+            widgets::Transport::Stop => Command::Stop,
+            widgets::Transport::Next => Command::Next,
+            widgets::Transport::Previous => Command::Previous,
+        };
+        command.apply(&mut self.state.lock().unwrap());
+    }
+
+    /// Places the buttons and sliders around the panel and refreshes what they show.
+    fn layout_widgets(&mut self) {
         let s_display = self.state.lock().unwrap();
-        self.overlay = text::Overlay::new_from(vec![
-            //&format!("FPS: {}.", self.time_state.fps + 1),
-            &format!("[Space] to pause or continue."),
-            &format!("[S] to skip, [Esc] to exit."),
-            &s_display.message,
-        ]);
-        self.gui = text::GUI::new_from(vec![
-            &format!("Current directory"),
-            &s_display.dir_name,
-            &format!("Now playing track <{}>", s_display.file_num),
-            &s_display.file_name,
-            &format!("Format <{}>", s_display.file_ext),
-        ], settings::WIDTH as f32, settings::HEIGHT as f32);
+        let playing = s_display.play;
+        let duration = s_display.duration as f32;
+        let volume = s_display.volume;
         drop(s_display);
-        self.gui.line_active[1] = 1;
-        self.gui.line_active[3] = 1;
+
+        widgets::layout_row(&mut self.widgets.buttons, self.gui.transport_rect(), 0.5 * self.gui.line_height);
+        self.widgets.buttons[1].label = if playing { "▮▮" } else { "▶" }.to_string();
+
+        let seek = self.widgets.slider_mut(widgets::SliderId::Seek);
+        seek.rect = self.gui.progress_rect();
+        seek.max = duration;
+        if !seek.dragging {
+            seek.value = self.position as f32;
+        }
+        let rect = self.gui.slider_rect(0);
+        let slider = self.widgets.slider_mut(widgets::SliderId::Volume);
+        // other front ends change the volume too
+        if !slider.dragging {
+            slider.value = volume;
+        }
+        let volume = slider;
+        volume.rect = rect;
+        volume.label = format!("Volume {:.0}%", volume.value * 100.0);
+        let rect = self.gui.slider_rect(1);
+        let gain = self.widgets.slider_mut(widgets::SliderId::Gain);
+        gain.rect = rect;
+        gain.label = format!("Gain {:.2}x", gain.value);
+    }
+
+    /// Applies what the user did to a button or a slider.
+    /// Seeking waits for the slider to be released, reopening the track on every move would stutter.
+    fn widget_event(&mut self, event: widgets::Event) {
+        match event {
+            widgets::Event::Clicked(action) => self.transport(action),
+            widgets::Event::Changed(widgets::SliderId::Seek, _) => {}
+            widgets::Event::Changed(id, value) | widgets::Event::Released(id, value) => {
+                let mut s_main = self.state.lock().unwrap();
+                match id {
+                    widgets::SliderId::Seek => Command::Seek(value as f64).apply(&mut s_main),
+                    widgets::SliderId::Volume => Command::Volume(value).apply(&mut s_main),
+                    widgets::SliderId::Gain => {
+                        s_main.notifications.info(format!("visualizer gain: {:.2}x", value));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Carries out a key binding. Playlist navigation only applies while the playlist is open;
+    /// character keys never get here while typing into the search box.
+    fn run(&mut self, action: Action) {
+        match action {
+            Action::PlayPause => self.transport(widgets::Transport::PlayPause),
+            Action::Next => self.transport(widgets::Transport::Next),
+            Action::Previous => self.transport(widgets::Transport::Previous),
+            Action::Stop => self.transport(widgets::Transport::Stop),
+            Action::SeekForward | Action::SeekBackward => {
+                let steps = if action == Action::SeekForward { 1.0 } else { -1.0 };
+                let event = self.widgets.step(widgets::SliderId::Seek, steps);
+                self.widget_event(event);
+            }
+            Action::VolumeUp | Action::VolumeDown => {
+                let steps = if action == Action::VolumeUp { 1.0 } else { -1.0 };
+                let event = self.widgets.step(widgets::SliderId::Volume, steps);
+                self.widget_event(event);
+            }
+            Action::ToggleTime => self.remaining = !self.remaining,
+            Action::PlayMode => {
+                let mut s_main = self.state.lock().unwrap();
+                let mode = s_main.play_mode.next();
+                Command::PlayMode(mode).apply(&mut s_main);
+            }
+            Action::NextTheme => {
+                let errors = self.themes.next();
+                let mut s_main = self.state.lock().unwrap();
+                for error in errors {
+                    s_main.notifications.error(format!("theme: {}", error));
+                }
+                s_main.notifications.info(format!("theme: {}", self.themes.theme.name));
+                drop(s_main);
+                self.apply_font();
+            }
+            Action::NextVisualizer => {
+                self.custom_list = shaders::scan_custom();
+                let index = (self.custom_index + 1) % (self.custom_list.len() + 1);
+                self.select_visualizer(index);
+            }
+            Action::Playlist => {
+                self.playlist_view = true;
+                self.help_view = false;
+                self.stats_view = false;
+                self.query.clear();
+                self.selected = self.state.lock().unwrap().track_index;
+            }
+            Action::Fullscreen => {
+                self.settings.full_screen = !self.settings.full_screen;
+                window::set_fullscreen(self.settings.full_screen);
+            }
+            Action::Presentation => {
+                self.presentation = !self.presentation;
+                self.caption_time = date::now();
+            }
+            Action::FocusNext => self.widgets.focus_next(),
+            Action::Decrease | Action::Increase => {
+                let steps = if action == Action::Increase { 1.0 } else { -1.0 };
+                if let Some(event) = self.widgets.step_focused(steps) {
+                    self.widget_event(event);
+                }
+            }
+            Action::Help => {
+                self.help_view = !self.help_view;
+                self.panel_scroll = 0;
+                self.playlist_view = false;
+                self.stats_view = false;
+            }
+            Action::Stats => {
+                self.stats_view = !self.stats_view;
+                self.panel_scroll = 0;
+                self.playlist_view = false;
+                self.help_view = false;
+                if self.stats_view {
+                    let db = history::db_path();
+                    self.stats_rows = match history::load(&db) {
+                        Ok(plays) => history::report(&plays, settings::STATS_ROWS, settings::STATS_ROWS),
+                        Err(error) => vec![format!("{}: {}", db.display(), error)],
+                    };
+                }
+            }
+            Action::SelectUp if self.help_view || self.stats_view => self.panel_scroll = self.panel_scroll.saturating_sub(1),
+            Action::SelectDown if self.help_view || self.stats_view => self.panel_scroll += 1,
+            Action::SelectUp if self.playlist_view => self.selected = self.selected.saturating_sub(1),
+            Action::SelectDown if self.playlist_view => self.selected += 1,
+            Action::PlaySelected if self.playlist_view => {
+                if let Some(&track) = self.results.get(self.selected) {
+                    Command::PlayTrack(track).apply(&mut self.state.lock().unwrap());
+                }
+            }
+            Action::Erase if self.playlist_view => {
+                self.query.pop();
+                self.selected = 0;
+            }
+            Action::Back => {
+                if self.presentation {
+                    self.presentation = false;
+                } else if self.help_view || self.stats_view {
+                    self.help_view = false;
+                    self.stats_view = false;
+                } else if !self.query.is_empty() {
+                    self.query.clear();
+                    self.selected = 0;
+                } else if self.playlist_view {
+                    self.playlist_view = false;
+                }
+            }
+            Action::Quit => miniquad::window::request_quit(),
+            Action::SelectUp | Action::SelectDown | Action::PlaySelected | Action::Erase => {}
+        }
+    }
+
+    /// Switches between the built-in bars (index 0) and the user shaders.
+    /// A shader that fails to compile leaves the bars in place and its log in the overlay.
+    fn select_visualizer(&mut self, index: usize) {
+        if let Some(pipeline) = self.custom_pipeline.take() {
+            self.ctx.delete_pipeline(pipeline);
+        }
+        if let Some(shader) = self.custom_shader.take() {
+            self.ctx.delete_shader(shader);
+        }
+        self.custom_error.clear();
+        self.custom_index = index;
+
+        if index == 0 {
+            self.state.lock().unwrap().notifications.info("visualizer: bars".to_string());
+            return;
+        }
+
+        let path = self.custom_list[index - 1].clone();
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let result = shaders::load_custom(&path).and_then(|fragment| {
+            self.ctx
+                .new_shader(
+                    miniquad::ShaderSource::Glsl {
+                        vertex: shaders::VERTEX_CUSTOM,
+                        fragment: &fragment,
+                    },
+                    shaders::meta_custom(),
+                )
+                .map_err(|e| e.to_string())
+        });
+
+        let mut s_main = self.state.lock().unwrap();
+        match result {
+            Ok(shader) => {
+                let pipeline = self.ctx.new_pipeline(
+                    &[BufferLayout::default()],
+                    &[
+                        VertexAttribute::new("pos", VertexFormat::Float3),
+                        VertexAttribute::new("uv", VertexFormat::Float2),
+                        VertexAttribute::new("act", VertexFormat::Float1),
+                    ],
+                    shader,
+                    PipelineParams::default(),
+                );
+                self.custom_shader = Some(shader);
+                self.custom_pipeline = Some(pipeline);
+                s_main.notifications.info(format!("visualizer: {}", name));
+            }
+            Err(error) => {
+                self.custom_error = error
+                    .lines()
+                    .map(|l| l.trim())
+                    .filter(|l| !l.is_empty())
+                    .take(settings::MAX_ERROR_LINES)
+                    .map(|l| l.to_string())
+                    .collect();
+                s_main.notifications.error(format!("shader error: {}", name));
+            }
+        }
+    }
+
+    /// One line per action from the binding table: its keys, padded to a column, and what it does.
+    fn help_rows(&self) -> Vec<String> {
+        let keys: Vec<String> = keymap::ACTIONS
+            .iter()
+            .map(|&(action, _, _, _)| {
+                let keys = self.input_state.keymap.keys(action);
+                if keys.is_empty() { "-".to_string() } else { keys.join(", ") }
+            })
+            .collect();
+        let column = keys.iter().map(|k| k.chars().count()).max().unwrap_or(0);
+        keymap::ACTIONS
+            .iter()
+            .zip(keys.iter())
+            .map(|(&(_, _, description, _), keys)| format!("{:<width$}  {}", keys, description, width = column))
+            .collect()
+    }
+
+    fn show_gui(&mut self) {
+        let mut s_display = self.state.lock().unwrap();
+
+        // The audio thread reports the position a few dozen times per second,
+        // in between the display advances with the frame clock
+        if s_display.position != self.reported {
+            self.reported = s_display.position;
+            self.position = s_display.position;
+        } else if s_display.play {
+            self.position = (self.position + self.time_state.frame_time).min(self.reported + self.config.playback.sampling_time);
+        }
+        if s_display.duration > 0.0 {
+            self.position = self.position.min(s_display.duration);
+        }
+
+        self.pulse = s_display.pulse;
+
+        let format_line = if s_display.tempo > 0.0 {
+            format!("Format <{}>, {:.0} BPM", s_display.file_ext, s_display.tempo)
+        } else {
+            format!("Format <{}>", s_display.file_ext)
+        };
+
+        let time_line = if s_display.duration <= 0.0 {
+            format!("{} / --:--", text::time_string(self.position))
+        } else if self.remaining {
+            format!("-{} / {}", text::time_string(s_display.duration - self.position), text::time_string(s_display.duration))
+        } else {
+            format!("{} / {}", text::time_string(self.position), text::time_string(s_display.duration))
+        };
+
+        let scale = self.themes.theme.font_scale * self.settings.ui_scale;
+        let hint = match self.input_state.keymap.keys(Action::Help).first() {
+            Some(keys) => format!("[{}] to list the keys.", keys),
+            None => format!("Key bindings in {}.", keymap::bindings_path().display()),
+        };
+        let chord = self.input_state.pending().map(|keys| format!("{} ...", keys));
+        let now = date::now();
+        if s_display.file_path != self.caption_path {
+            self.caption_path = s_display.file_path.clone();
+            self.caption_time = now;
+        }
+        // only a short now-playing caption in presentation mode, fading out at the bottom
+        let age = now - self.caption_time;
+        let caption = match s_display.tags.get(&s_display.file_path).and_then(|t| t.display()) {
+            Some(name) => format!("Now playing: {}", name),
+            None => format!("Now playing: {}", s_display.file_name),
+        };
+        let toasts: Vec<(String, f32)> = s_display
+            .notifications
+            .visible(Instant::now())
+            .iter()
+            .map(|n| (format!("{}{}", n.severity.prefix(), n.text), n.alpha(Instant::now())))
+            .collect();
+        let mut overlay_lines = vec![hint.as_str()];
+        let mut alpha = vec![1.0];
+        if let Some(chord) = &chord {
+            overlay_lines.push(chord.as_str());
+            alpha.push(1.0);
+        }
+        for (toast, a) in toasts.iter() {
+            overlay_lines.push(toast.as_str());
+            alpha.push(*a);
+        }
+        overlay_lines.extend(self.custom_error.iter().map(|l| l.as_str()));
+        alpha.resize(overlay_lines.len(), 1.0);
+        if self.presentation {
+            overlay_lines = if age < settings::CAPTION_TIME { vec![caption.as_str()] } else { Vec::new() };
+            let fade = (settings::CAPTION_TIME - age) / settings::CAPTION_FADE;
+            alpha = vec![fade.clamp(0.0, 1.0) as f32; overlay_lines.len()];
+        }
+        self.overlay = text::Overlay::new_from(overlay_lines, self.settings.screen_width_f, scale, &mut self.fonts);
+        self.overlay.line_alpha = alpha;
+        self.overlay.fit(now - self.start_time, &mut self.fonts);
+        if self.presentation && !self.overlay.lines.is_empty() {
+            self.overlay.line_y[0] = self.settings.screen_height_f - 2.0 * text::HEIGHT * scale;
+        }
+        if self.help_view || self.stats_view {
+            drop(s_display);
+            let (title, rows) = if self.help_view {
+                ("Key bindings", self.help_rows())
+            } else {
+                ("Listening statistics", self.stats_rows.clone())
+            };
+            let count = rows.len();
+            self.panel_scroll = self.panel_scroll.min(count.saturating_sub(settings::PLAYLIST_ROWS));
+            let shown = self.panel_scroll..(self.panel_scroll + settings::PLAYLIST_ROWS).min(count);
+            let mut lines = vec![title.to_string()];
+            lines.extend(rows[shown.clone()].iter().cloned());
+            if shown.end < count {
+                lines.push("…".to_string());
+            }
+            self.gui = text::GUI::new_from(lines.iter().map(|l| l.as_str()).collect(), self.settings.screen_width_f, self.settings.screen_height_f, scale, &mut self.fonts);
+            self.gui.centered = false;
+            self.gui.line_active[0] = 1;
+        } else if self.playlist_view {
+            let stale = self.searched.as_ref().is_none_or(|(query, playlist, tags)| {
+                *query != self.query || *playlist != s_display.playlist || *tags != s_display.tags.len()
+            });
+            if stale {
+                self.results = search::filter(&self.query, &s_display.playlist, &s_display.tags);
+                self.searched = Some((self.query.clone(), s_display.playlist.clone(), s_display.tags.len()));
+            }
+            let count = self.results.len();
+            self.selected = self.selected.min(count.saturating_sub(1));
+            if self.selected < self.scroll {
+                self.scroll = self.selected;
+            }
+            if self.selected >= self.scroll + settings::PLAYLIST_ROWS {
+                self.scroll = self.selected + 1 - settings::PLAYLIST_ROWS;
+            }
+            self.scroll = self.scroll.min(count.saturating_sub(settings::PLAYLIST_ROWS));
+            let rows = self.scroll..(self.scroll + settings::PLAYLIST_ROWS).min(count);
+
+            let mut lines = if self.query.is_empty() {
+                vec![format!("Playlist, {} tracks, type to search", count)]
+            } else {
+                vec![format!("Search: {}_ ({} of {})", self.query, count, s_display.playlist.len())]
+            };
+            for i in rows.clone() {
+                let track = self.results[i];
+                let path = &s_display.playlist[track];
+                let name = match s_display.tags.get(path).and_then(|t| t.display()) {
+                    Some(name) => name,
+                    None => path.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+                };
+                let cursor = if i == self.selected { "▸" } else { " " };
+                lines.push(format!("{} {:>3}. {}", cursor, track + 1, name));
+            }
+            if rows.end < count {
+                lines.push("…".to_string());
+            }
+            let current = s_display.track_index;
+            drop(s_display);
+            self.gui = text::GUI::new_from(lines.iter().map(|l| l.as_str()).collect(), self.settings.screen_width_f, self.settings.screen_height_f, scale, &mut self.fonts);
+            self.gui.centered = false;
+            for i in rows {
+                if self.results[i] == current {
+                    self.gui.line_active[i - self.scroll + 1] = 1;
+                }
+            }
+        } else {
+            self.gui = text::GUI::new_from(vec![
+                &format!("Current directory"),
+                &s_display.dir_name,
+                &format!("Now playing track <{}>", s_display.file_num),
+                &s_display.file_name,
+                &format_line,
+                &time_line,
+            ], self.settings.screen_width_f, self.settings.screen_height_f, scale, &mut self.fonts);
+            drop(s_display);
+            self.gui.line_active[1] = 1;
+            self.gui.line_active[3] = 1;
+            self.gui.line_fit[1] = text::Fit::Middle;
+            self.gui.line_fit[3] = text::Fit::Marquee;
+        }
+        self.gui.fit(date::now() - self.start_time, &mut self.fonts);
+        self.overlay.font_col = self.themes.theme.overlay_text;
+        self.gui.font_col = self.themes.theme.text;
+        self.gui.act_col = self.themes.theme.active_text;
     }
 }
 
@@ -332,49 +1001,67 @@ impl EventHandler for Stage {
     // ============================
 
     fn update(&mut self) {
-        self.time_state.frame_time();
+        self.time_state.frame_time(self.config.display.frame_time());
+        self.fonts.next_frame();
 
         self.show_gui();
 
-        if self.input_state.keys.esc {
-            miniquad::window::quit()
+        let actions: Vec<Action> = self.input_state.actions.drain(..).collect();
+        for action in actions {
+            self.run(action);
         }
 
-        if self.input_state.keys.s && self.input_state.apply_change {
+        if let Some(result) = self.themes.poll(date::now()) {
             let mut s_main = self.state.lock().unwrap();
-            if s_main.play {
-                s_main.skip = true;
-                s_main.message = format!("*** track skipped")
-            } else {
-                s_main.message = format!("*** unpause to skip")
+            match result {
+                Ok(()) => s_main.notifications.info(format!("theme reloaded: {}", self.themes.theme.name)),
+                Err(error) => s_main.notifications.error(format!("theme: {}", error)),
             }
             drop(s_main);
-            self.input_state.apply_change = false;
+            self.apply_font();
         }
 
-        if self.input_state.keys.space && self.input_state.apply_change {
-            let mut s_main = self.state.lock().unwrap();
-            s_main.play = !s_main.play;
-            s_main.message = format!("*** paused: {}", !s_main.play);
-            drop(s_main);
-            self.input_state.apply_change = false;
+        if let Some(result) = self.config_watch.poll(date::now()) {
+            self.reload_config(result);
         }
         
         self.mesh[0] = mesh::Mesh::new_overlay(
             &self.overlay,
-            1.0 / settings::WIDTH as f32,
-            1.0 / settings::HEIGHT as f32,
+            &mut self.fonts,
+            1.0 / self.settings.screen_width_f,
+            1.0 / self.settings.screen_height_f,
         );
+        self.layout_widgets();
         self.mesh[1] = mesh::Mesh::new_gui(
             &self.gui,
-            1.0 / settings::WIDTH as f32,
-            1.0 / settings::HEIGHT as f32,
+            &self.widgets,
+            &mut self.fonts,
+            1.0 / self.settings.screen_width_f,
+            1.0 / self.settings.screen_height_f,
         );
 
-        self.mesh[3] = mesh::Mesh::new_screen(self.settings.screen_width_f/self.settings.screen_height_f);
+        self.upload_font();
 
         let s_display = self.state.lock().unwrap();
 
+        self.progress = if s_display.duration > 0.0 {
+            (self.position / s_display.duration) as f32
+        } else {
+            0.0
+        };
+        self.mesh[5] = mesh::Mesh::new_sliders(
+            &self.widgets.sliders,
+            1.0 / self.settings.screen_width_f,
+            1.0 / self.settings.screen_height_f,
+        );
+        self.mesh[4] = mesh::Mesh::new_waveform(
+            s_display.waveform.as_ref(),
+            self.progress,
+            self.gui.waveform_rect(),
+            1.0 / self.settings.screen_width_f,
+            1.0 / self.settings.screen_height_f,
+        );
+
         let mut average_visuals = [0i16; settings::SAMPLES];
 
         for l in 0..settings::SAMPLES {
@@ -386,18 +1073,34 @@ impl EventHandler for Stage {
         
         drop(s_display);
 
+        self.spectrum.update(&average_visuals);
+
+        let mut bytes = [255u8; spectrum::SPECTRUM_BINS * 4];
+        for (k, level) in self.spectrum.bins.iter().enumerate() {
+            let v = (level * 255.0) as u8;
+            bytes[4 * k..4 * k + 3].copy_from_slice(&[v, v, v]);
+        }
+        self.ctx.texture_update(self.bindings[6].images[0], &bytes);
+        for k in 0..spectrum::SPECTRUM_BINS {
+            let sample = average_visuals[k * settings::SAMPLES / spectrum::SPECTRUM_BINS];
+            let v = ((sample as f32 / 32768.0 * 0.5 + 0.5) * 255.0) as u8;
+            bytes[4 * k..4 * k + 3].copy_from_slice(&[v, v, v]);
+        }
+        self.ctx.texture_update(self.bindings[6].images[1], &bytes);
+
         self.other_counter += 1.0;
 
-        if self.other_counter > settings::SAMPLING_TIME/settings::FT_DESIRED {
+        if self.other_counter > self.config.playback.sampling_time / self.config.display.frame_time() {
             self.other_counter = 0.0
         }
 
-        self.mesh[2] = mesh::Mesh::new_visuals(&average_visuals);
+        let gain = self.widgets.slider(widgets::SliderId::Gain).value;
+        self.mesh[2] = mesh::Mesh::new_visuals(&average_visuals, gain * (1.0 + 0.25 * self.pulse), self.config.display.bars);
 
         self.counter += 1;
 
         if self.counter > settings::AVERAGE_TIME-1 {
-            self.mesh[2] = mesh::Mesh::new_visuals(&average_visuals);
+            self.mesh[2] = mesh::Mesh::new_visuals(&average_visuals, gain * (1.0 + 0.25 * self.pulse), self.config.display.bars);
             self.counter = 0
         }
         
@@ -409,7 +1112,13 @@ impl EventHandler for Stage {
 
     fn draw(&mut self) {
 
-        self.ctx.begin_default_pass(PassAction::clear_color(settings::CLR4.0, settings::CLR4.1, settings::CLR4.2, 1.0));
+        let theme = &self.themes.theme;
+
+        // beats flash the background and the panel
+        let background = theme::mix(theme.background, theme.accent, 0.15 * self.pulse);
+        let panel = theme::mix(theme.panel, (1.0, 1.0, 1.0, 1.0), 0.3 * self.pulse);
+
+        self.ctx.begin_default_pass(PassAction::clear_color(background.0, background.1, background.2, 1.0));
 
         self.ctx.apply_pipeline(&self.pipeline[3]);
 
@@ -424,23 +1133,43 @@ impl EventHandler for Stage {
         self.ctx.end_render_pass();
 
         self.ctx
-            .begin_pass(Some(self.render_pass), PassAction::clear_color(settings::CLR4.0, settings::CLR4.1, settings::CLR4.2, 1.0));
+            .begin_pass(Some(self.render_pass), PassAction::clear_color(background.0, background.1, background.2, 1.0));
 
-        for j in 0..4 {
+        for j in 0..self.mesh.len() {
             self.ctx.buffer_update(self.bindings[j].vertex_buffers[0], BufferSource::slice(&self.mesh[j].vertices));
             self.ctx.buffer_update(self.bindings[j].index_buffer, BufferSource::slice(&self.mesh[j].indices));
         }
 
-        self.ctx.apply_pipeline(&self.pipeline[2]);
+        match &self.custom_pipeline {
+            Some(pipeline) => {
+                self.ctx.apply_pipeline(pipeline);
 
-        self.ctx.apply_bindings(&self.bindings[2]);
+                self.ctx.apply_bindings(&self.bindings[6]);
 
-        self.ctx
-            .apply_uniforms(miniquad::UniformsSource::table(&shaders::UniformsVisuals {
-                fontcolor: settings::CLR8,
-            }));
+                self.ctx
+                    .apply_uniforms(miniquad::UniformsSource::table(&shaders::UniformsCustom {
+                        time: (date::now() - self.start_time) as f32,
+                        resolution: (self.settings.screen_width_f, self.settings.screen_height_f, 1.0),
+                        beat: self.pulse,
+                        progress: self.progress,
+                    }));
+
+                self.ctx.draw(0, self.mesh[6].num * 6, 1);
+            }
+            None => {
+                self.ctx.apply_pipeline(&self.pipeline[2]);
 
-        self.ctx.draw(0, self.mesh[2].num * 6, 1); 
+                self.ctx.apply_bindings(&self.bindings[2]);
+
+                self.ctx
+                    .apply_uniforms(miniquad::UniformsSource::table(&shaders::UniformsVisuals {
+                        lowcolor: theme.visualizer_low,
+                        highcolor: theme.visualizer_high,
+                    }));
+
+                self.ctx.draw(0, self.mesh[2].num * 6, 1);
+            }
+        }
         
 
         self.ctx.apply_pipeline(&self.pipeline[0]);
@@ -454,17 +1183,44 @@ impl EventHandler for Stage {
 
         self.ctx.draw(0, self.mesh[0].num * 6, 1);
 
-        self.ctx.apply_pipeline(&self.pipeline[1]);
+        // presentation mode leaves the screen to the visualizer and the caption
+        if !self.presentation {
+            self.ctx.apply_pipeline(&self.pipeline[1]);
 
-        self.ctx.apply_bindings(&self.bindings[1]);
+            self.ctx.apply_bindings(&self.bindings[1]);
 
-        self.ctx
-            .apply_uniforms(miniquad::UniformsSource::table(&shaders::UniformsGUI {
-                fontcolor: self.gui.font_col,
-                actcolor: self.gui.act_col,
-            }));
+            self.ctx
+                .apply_uniforms(miniquad::UniformsSource::table(&shaders::UniformsGUI {
+                    fontcolor: self.gui.font_col,
+                    actcolor: self.gui.act_col,
+                    panelcolor: panel,
+                }));
+
+            self.ctx.draw(0, self.mesh[1].num * 6, 1); 
+
+            self.ctx.apply_pipeline(&self.pipeline[4]);
+
+            self.ctx.apply_bindings(&self.bindings[4]);
+
+            self.ctx
+                .apply_uniforms(miniquad::UniformsSource::table(&shaders::UniformsWaveform {
+                    fontcolor: theme.overlay_text,
+                    actcolor: theme.accent,
+                }));
 
-        self.ctx.draw(0, self.mesh[1].num * 6, 1); 
+            self.ctx.draw(0, self.mesh[4].num * 6, 1);
+
+            // The sliders share the flat-color pipeline of the waveform
+            self.ctx.apply_bindings(&self.bindings[5]);
+
+            self.ctx
+                .apply_uniforms(miniquad::UniformsSource::table(&shaders::UniformsWaveform {
+                    fontcolor: theme.text,
+                    actcolor: theme.active_text,
+                }));
+
+            self.ctx.draw(0, self.mesh[5].num * 6, 1);
+        }
 
         self.ctx.end_render_pass();
 
@@ -477,19 +1233,84 @@ impl EventHandler for Stage {
     // INPUT HANDLING
     // ============================
 
-    fn key_down_event(&mut self, keycode: KeyCode, _keymods: KeyMods, _repeat: bool) {
-        self.input_state.keys.read_key(keycode, true);
-        if !self.input_state.apply_change {
-            self.input_state.apply_change = true
+    fn key_down_event(&mut self, keycode: KeyCode, keymods: KeyMods, repeat: bool) {
+        // while the playlist is open, typing goes to the search box instead of the letter keys
+        let query = self.playlist_view.then_some(self.query.as_str());
+        self.input_state.key_down(keycode, keymods, repeat, query);
+    }
+
+    fn char_event(&mut self, character: char, _keymods: KeyMods, _repeat: bool) {
+        if !self.playlist_view || character.is_control() || (character == ' ' && self.query.is_empty()) {
+            return;
+        }
+        self.query.push(character);
+        self.selected = 0;
+    }
+
+    fn mouse_button_down_event(&mut self, button: MouseButton, x: f32, y: f32) {
+        if button != MouseButton::Left || self.presentation {
+            return;
+        }
+        if let Some(event) = self.widgets.mouse_down(x, y) {
+            self.widget_event(event);
+            return;
+        }
+        let (x1, y1, x2, y2) = self.gui.waveform_rect();
+        if x >= x1 && x <= x2 && y >= y1 && y <= y2 {
+            let mut s_main = self.state.lock().unwrap();
+            let target = (x - x1) as f64 / (x2 - x1) as f64 * s_main.duration;
+            Command::Seek(target).apply(&mut s_main);
+        }
+    }
+
+    fn mouse_button_up_event(&mut self, button: MouseButton, x: f32, y: f32) {
+        if button != MouseButton::Left || self.presentation {
+            return;
+        }
+        if let Some(event) = self.widgets.mouse_up(x, y) {
+            self.widget_event(event);
         }
     }
 
-    fn key_up_event(&mut self, keycode: KeyCode, _keymods: KeyMods) {
-        self.input_state.keys.read_key(keycode, false);
-        self.input_state.apply_change = false
+    fn mouse_motion_event(&mut self, x: f32, y: f32) {
+        if self.presentation {
+            return;
+        }
+        if let Some(event) = self.widgets.mouse_move(x, y) {
+            self.widget_event(event);
+        }
+    }
+
+    fn quit_requested_event(&mut self) {
+        session::save(&self.state);
+        #[cfg(unix)]
+        control::remove_socket();
+    }
+
+    fn mouse_wheel_event(&mut self, _x: f32, y: f32) {
+        if y == 0.0 || self.presentation {
+            return;
+        }
+        if let Some(event) = self.widgets.wheel(y) {
+            self.widget_event(event);
+        } else if self.help_view || self.stats_view {
+            if y > 0.0 {
+                self.panel_scroll = self.panel_scroll.saturating_sub(1);
+            } else {
+                self.panel_scroll += 1;
+            }
+        } else if self.playlist_view {
+            // scroll the selection, wheel up moves towards the top of the list
+            if y > 0.0 {
+                self.selected = self.selected.saturating_sub(1);
+            } else {
+                self.selected += 1;
+            }
+        }
     }
 
     fn resize_event(&mut self, width: f32, height: f32) {
-        self.settings.screen_change(width, height);
+        self.settings.screen_change(width, height, window::dpi_scale());
+        self.resize_target();
     }
 }
\ No newline at end of file
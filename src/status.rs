@@ -0,0 +1,168 @@
+use serde::Serialize;
+
+use crate::config::PlayMode;
+use crate::settings;
+use crate::State;
+
+/// What remote front ends see of the player, serialized as JSON.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Status {
+    /// "playing", "paused", or "stopped" after a stop or when there is nothing to play.
+    pub state: &'static str,
+    /// Index into the playlist of the current track.
+    pub index: usize,
+    pub path: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    /// Seconds.
+    pub position: f64,
+    pub duration: f64,
+    /// 0.0 to 1.0.
+    pub volume: f32,
+    pub play_mode: PlayMode,
+    pub queue: Vec<String>,
+}
+
+impl Status {
+    pub fn of(state: &State) -> Status {
+        let tags = state.tags.get(&state.file_path).cloned().unwrap_or_default();
+        let title = if tags.title.is_empty() { state.file_name.clone() } else { tags.title };
+        Status {
+            state: if state.playlist.is_empty() || state.stopped {
+                "stopped"
+            } else if state.play {
+                "playing"
+            } else {
+                "paused"
+            },
+            index: state.track_index,
+            path: state.file_path.display().to_string(),
+            title,
+            artist: tags.artist,
+            album: tags.album,
+            position: state.position,
+            duration: state.duration,
+            volume: state.volume,
+            play_mode: state.play_mode,
+            queue: state.queue.iter().map(|p| p.display().to_string()).collect(),
+        }
+    }
+
+    /// The events between `last` and this status, taken `elapsed` seconds apart: "track",
+    /// "state", "volume", "play_mode", "queue", and "seek" when the position jumped.
+    pub fn events(&self, last: &Status, elapsed: f64) -> Vec<&'static str> {
+        let mut events = Vec::new();
+        if self.path != last.path || self.title != last.title || self.duration != last.duration {
+            events.push("track");
+        }
+        if self.state != last.state {
+            events.push("state");
+        }
+        if self.volume != last.volume {
+            events.push("volume");
+        }
+        if self.play_mode != last.play_mode {
+            events.push("play_mode");
+        }
+        if self.queue != last.queue {
+            events.push("queue");
+        }
+        let elapsed = if last.state == "playing" { elapsed } else { 0.0 };
+        if self.path == last.path && (self.position - last.position - elapsed).abs() > settings::SEEK_JUMP {
+            events.push("seek");
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::config::Config;
+
+    fn playing() -> Status {
+        let mut state = State::new(Config::default());
+        state.playlist = vec![PathBuf::from("/music/01.flac"), PathBuf::from("/music/02.flac")];
+        state.file_path = state.playlist[0].clone();
+        state.file_name = "01".to_string();
+        state.duration = 200.0;
+        state.position = 30.0;
+        state.play = true;
+        Status::of(&state)
+    }
+
+    #[test]
+    fn state_of_the_player() {
+        let mut state = State::new(Config::default());
+        assert_eq!(Status::of(&state).state, "stopped");
+        state.playlist = vec![PathBuf::from("/music/01.flac")];
+        state.play = false;
+        assert_eq!(Status::of(&state).state, "paused");
+        state.play = true;
+        assert_eq!(Status::of(&state).state, "playing");
+        state.stopped = true;
+        assert_eq!(Status::of(&state).state, "stopped");
+    }
+
+    #[test]
+    fn title_falls_back_to_the_file_name() {
+        assert_eq!(playing().title, "01");
+    }
+
+    #[test]
+    fn no_events_while_playing_on() {
+        let last = playing();
+        let mut status = last.clone();
+        status.position += 0.5;
+        assert!(status.events(&last, 0.5).is_empty());
+    }
+
+    #[test]
+    fn events_of_each_change() {
+        let last = playing();
+
+        let mut status = last.clone();
+        status.path = "/music/02.flac".to_string();
+        status.index = 1;
+        status.position = 0.0;
+        assert_eq!(status.events(&last, 0.5), ["track"]);
+
+        let mut status = last.clone();
+        status.state = "paused";
+        assert_eq!(status.events(&last, 0.0), ["state"]);
+
+        let mut status = last.clone();
+        status.volume = 0.5;
+        status.position += 0.5;
+        assert_eq!(status.events(&last, 0.5), ["volume"]);
+
+        let mut status = last.clone();
+        status.play_mode = PlayMode::Once;
+        status.position += 0.5;
+        assert_eq!(status.events(&last, 0.5), ["play_mode"]);
+
+        let mut status = last.clone();
+        status.queue = vec!["/music/02.flac".to_string()];
+        status.position += 0.5;
+        assert_eq!(status.events(&last, 0.5), ["queue"]);
+    }
+
+    #[test]
+    fn seek_is_a_jump_in_position() {
+        let last = playing();
+        let mut status = last.clone();
+        status.position = 100.0;
+        assert_eq!(status.events(&last, 0.5), ["seek"]);
+
+        // paused, the position should stand still however long it was
+        let mut paused = last.clone();
+        paused.state = "paused";
+        let mut status = paused.clone();
+        status.position += 5.0;
+        assert_eq!(status.events(&paused, 5.0), ["seek"]);
+        assert!(paused.events(&paused, 5.0).is_empty());
+    }
+}
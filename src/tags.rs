@@ -0,0 +1,109 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, StandardTagKey, Tag};
+use symphonia::core::probe::Hint;
+
+/// The tag fields the player shows and searches.
+#[derive(Debug, Clone, Default)]
+pub struct Tags {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    /// Track number as tagged, e.g. `3` or `3/12`.
+    pub track: String,
+}
+
+impl Tags {
+    /// Reads ID3, Vorbis comment and RIFF INFO tags; missing fields stay empty.
+    pub fn read(path: &Path) -> Tags {
+        let mut tags = Tags::default();
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => return tags,
+        };
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(ext);
+        }
+        let probe = symphonia::default::get_probe().format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default());
+        let mut probed = match probe {
+            Ok(probed) => probed,
+            Err(_) => return tags,
+        };
+        // tags in front of the container (ID3v2) first, then the container's own
+        if let Some(metadata) = probed.metadata.get() {
+            if let Some(revision) = metadata.current() {
+                tags.apply(revision.tags());
+            }
+        }
+        if let Some(revision) = probed.format.metadata().current() {
+            tags.apply(revision.tags());
+        }
+        tags
+    }
+
+    fn apply(&mut self, list: &[Tag]) {
+        for tag in list {
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::Album) => &mut self.album,
+                Some(StandardTagKey::TrackNumber) => &mut self.track,
+                _ => continue,
+            };
+            *field = tag.value.to_string().trim().to_string();
+        }
+    }
+
+    /// `Artist - Title`, or just the title; `None` without a title.
+    pub fn display(&self) -> Option<String> {
+        if self.title.is_empty() {
+            None
+        } else if self.artist.is_empty() {
+            Some(self.title.clone())
+        } else {
+            Some(format!("{} - {}", self.artist, self.title))
+        }
+    }
+}
+
+/// Image file next to the track that is commonly used as album art, like `cover.jpg` or `Folder.png`.
+pub fn cover_art(track: &Path) -> Option<PathBuf> {
+    let dir = track.parent()?;
+    let mut images: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            let ext = p.extension().and_then(|x| x.to_str()).unwrap_or_default().to_ascii_lowercase();
+            let stem = p.file_stem().and_then(|x| x.to_str()).unwrap_or_default().to_ascii_lowercase();
+            matches!(ext.as_str(), "jpg" | "jpeg" | "png") && COVER_NAMES.contains(&stem.as_str())
+        })
+        .collect();
+    images.sort_by_key(|p| {
+        let stem = p.file_stem().and_then(|x| x.to_str()).unwrap_or_default().to_ascii_lowercase();
+        COVER_NAMES.iter().position(|n| *n == stem)
+    });
+    images.into_iter().next()
+}
+
+const COVER_NAMES: &[&str] = &["cover", "folder", "front", "album", "albumart"];
+
+/// Reads the tags of `paths` on a background thread into `State::tags`,
+/// skipping files that were read before.
+pub fn scan(state: Arc<Mutex<crate::State>>, paths: Vec<PathBuf>) {
+    thread::spawn(move || {
+        for path in paths {
+            if state.lock().unwrap().tags.contains_key(&path) {
+                continue;
+            }
+            let tags = Tags::read(&path);
+            state.lock().unwrap().tags.insert(path, tags);
+        }
+    });
+}
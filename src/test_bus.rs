@@ -0,0 +1,77 @@
+//! A private session bus for the D-Bus tests, so they neither need nor disturb the desktop's.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use zbus::blocking::connection;
+
+static BUSES: AtomicUsize = AtomicUsize::new(0);
+
+/// A `dbus-daemon` of its own, stopped and cleaned up on drop.
+pub struct TestBus {
+    daemon: Child,
+    /// Holds the socket; tests may keep their files here too, it goes with the bus.
+    pub dir: PathBuf,
+    pub address: String,
+}
+
+impl TestBus {
+    /// `None` where `dbus-daemon` is not installed; the caller then skips its test.
+    pub fn start() -> Option<TestBus> {
+        let dir = std::env::temp_dir().join(format!("srmp-bus-{}-{}", std::process::id(), BUSES.fetch_add(1, Ordering::SeqCst)));
+        fs::create_dir_all(&dir).ok()?;
+        let socket = dir.join("bus");
+        let config = dir.join("bus.conf");
+        fs::write(&config, format!(
+            r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN" "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig><type>session</type><listen>unix:path={}</listen><auth>EXTERNAL</auth>
+<policy context="default"><allow send_destination="*"/><allow receive_sender="*"/><allow own="*"/></policy></busconfig>"#,
+            socket.display()
+        )).ok()?;
+        let Ok(daemon) = Command::new("dbus-daemon")
+            .arg(format!("--config-file={}", config.display()))
+            .arg("--nofork")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            eprintln!("dbus-daemon not found, skipping");
+            let _ = fs::remove_dir_all(&dir);
+            return None;
+        };
+        let bus = TestBus { daemon, dir, address: format!("unix:path={}", socket.display()) };
+        let started = Instant::now();
+        while !socket.exists() {
+            assert!(started.elapsed() < Duration::from_secs(5), "dbus-daemon did not start");
+            thread::sleep(Duration::from_millis(20));
+        }
+        Some(bus)
+    }
+
+    pub fn connect(&self) -> connection::Builder<'static> {
+        connection::Builder::address(self.address.as_str()).expect("test bus address")
+    }
+}
+
+impl Drop for TestBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Waits up to five seconds for `check` to hold.
+pub fn eventually(mut check: impl FnMut() -> bool) -> bool {
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_secs(5) {
+        if check() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
@@ -0,0 +1,47 @@
+//! Small WAV files written on the fly for the decoding tests.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// A 16-bit PCM WAV file of interleaved `samples` in the temp directory, removed on drop.
+pub struct TestWav {
+    pub path: PathBuf,
+}
+
+impl TestWav {
+    pub fn new(rate: u32, channels: u16, samples: &[i16]) -> TestWav {
+        let path = std::env::temp_dir().join(format!("srmp-{}-{}.wav", std::process::id(), FILES.fetch_add(1, Ordering::SeqCst)));
+        write(&path, rate, channels, samples);
+        TestWav { path }
+    }
+}
+
+impl Drop for TestWav {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn write(path: &Path, rate: u32, channels: u16, samples: &[i16]) {
+    let data = (samples.len() * 2) as u32;
+    let mut bytes = Vec::with_capacity(44 + data as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&channels.to_le_bytes());
+    bytes.extend_from_slice(&rate.to_le_bytes());
+    bytes.extend_from_slice(&(rate * channels as u32 * 2).to_le_bytes());
+    bytes.extend_from_slice(&(channels * 2).to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data.to_le_bytes());
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    fs::write(path, bytes).unwrap();
+}
@@ -1,36 +1,40 @@
-use crate::{mesh::TextureUV, settings};
-use std::char;
+use crate::font::Fonts;
+use crate::settings;
+use crate::theme::Theme;
 
-pub const WIDTH: f32 = 12.0;
 pub const HEIGHT: f32 = 20.0;
-pub const HEIGHT_CAP: f32 = 20.0;
-const X1: f32 = 3.0;
-const Y1: f32 = 2.0;
-const Y2: f32 = Y1+HEIGHT_CAP;
-const Y3: f32 = Y2+HEIGHT_CAP;
-const Y4: f32 = Y3+HEIGHT;
-const Y5: f32 = Y4+HEIGHT;
-const Y6: f32 = Y5+HEIGHT;
-const TEXWIDTH: f32 = 256.0;
-const TEXHEIGHT: f32 = 256.0;
+const ELLIPSIS: char = '…';
+
+/// How a line wider than the space available to it is shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fit {
+    /// Drawn as is, running past the edge.
+    Overflow,
+    /// Cut at the end with an ellipsis.
+    Ellipsis,
+    /// Cut in the middle, keeping both ends of a path.
+    Middle,
+    /// Scrolled back and forth inside the available width.
+    Marquee,
+}
 
 pub struct Overlay {
     pub lines: Vec<String>,
     pub line_width: Vec<f32>,
     pub line_x: Vec<f32>,
     pub line_y: Vec<f32>,
+    pub line_fit: Vec<Fit>,
+    pub line_scroll: Vec<f32>,
+    pub line_alpha: Vec<f32>,
+    pub fit_width: f32,
     pub font_col: (f32, f32, f32, f32),
-    pub x0: f32,
-    pub y0: f32,
     pub scale: f32,
-    pub line_height: f32,
 }
 
 impl Overlay {
-    pub fn new_from(lines: Vec<&str>) -> Overlay {
+    pub fn new_from(lines: Vec<&str>, width: f32, scale: f32, fonts: &mut Fonts) -> Overlay {
         let x0 = 20.0;
         let y0 = 20.0;
-        let scale = 1.0;
         let line_height = HEIGHT*scale;
         let mut lines1 = Vec::new();
         let mut line_width = Vec::new();
@@ -38,8 +42,7 @@ impl Overlay {
         let mut line_y = Vec::new();
         for l in 0..lines.len() {
             lines1.push(lines[l].to_string());
-            let letters: Vec<char> = lines[l].chars().collect();
-            line_width.push(letters.len() as f32 * WIDTH * scale);
+            line_width.push(fonts.text_width(lines[l], scale));
             line_x.push(x0*scale);
             line_y.push(y0*scale + (l as f32)*line_height)
         }
@@ -48,14 +51,30 @@ impl Overlay {
             line_width,
             line_x,
             line_y,
-            font_col: settings::CLR8,
-            x0,
-            y0,
+            line_fit: vec![Fit::Ellipsis; lines.len()],
+            line_scroll: vec![0.0; lines.len()],
+            line_alpha: vec![1.0; lines.len()],
+            fit_width: width - 2.0*x0*scale,
+            font_col: Theme::default().overlay_text,
             scale,
-            line_height,
         };
         overlay
     }
+
+    /// Applies `line_fit` to every line; `time` drives the marquee.
+    pub fn fit(&mut self, time: f64, fonts: &mut Fonts) {
+        for l in 0..self.lines.len() {
+            let (line, width, scroll) = fit_text(&self.lines[l], self.line_fit[l], self.fit_width, self.scale, time, fonts);
+            self.lines[l] = line;
+            self.line_width[l] = width;
+            self.line_scroll[l] = scroll;
+        }
+    }
+
+    /// Horizontal range glyphs of line `l` are clipped to, if any.
+    pub fn clip(&self, l: usize) -> Option<(f32, f32)> {
+        clip_range(self.line_fit[l], self.line_x[l], self.line_width[l])
+    }
 }
 
 pub struct GUI {
@@ -64,20 +83,20 @@ pub struct GUI {
     pub line_x: Vec<f32>,
     pub line_y: Vec<f32>,
     pub line_active: Vec<i32>,
+    pub line_fit: Vec<Fit>,
+    pub line_scroll: Vec<f32>,
+    pub fit_width: f32,
     pub font_col: (f32, f32, f32, f32),
     pub act_col: (f32, f32, f32, f32),
-    pub act_no: usize,
     pub x0: f32,
-    pub y0: f32,
     pub max_width: f32,
     pub scale: f32,
     pub line_height: f32,
-    pub show: bool,
+    pub centered: bool,
 }
 
 impl GUI {
-    pub fn new_from(lines: Vec<&str>, width: f32, height: f32) -> GUI {
-        let scale = 1.0;
+    pub fn new_from(lines: Vec<&str>, width: f32, height: f32, scale: f32, fonts: &mut Fonts) -> GUI {
         let line_height = HEIGHT*scale;
         let x0 = width*0.5;
         let y0 = (height - (lines.len() as f32 - 1.0)*line_height)*0.3/scale;
@@ -88,8 +107,7 @@ impl GUI {
         let mut line_active = Vec::new();
         for l in 0..lines.len() {
             lines1.push(lines[l].to_string());
-            let letters: Vec<char> = lines[l].chars().collect();
-            line_width.push(letters.len() as f32 * WIDTH * scale);
+            line_width.push(fonts.text_width(lines[l], scale));
             line_x.push(x0*scale);
             line_y.push(y0*scale + (l as f32)*line_height);
             line_active.push(0);
@@ -101,210 +119,170 @@ impl GUI {
             line_x,
             line_y,
             line_active,
-            font_col: settings::CLR2,
-            act_col: settings::CLR6,
-            act_no: 0,
+            line_fit: vec![Fit::Ellipsis; lines.len()],
+            line_scroll: vec![0.0; lines.len()],
+            fit_width: settings::GUI_TEXT_WIDTH*width.min(height)/settings::HEIGHT as f32,
+            font_col: Theme::default().text,
+            act_col: Theme::default().active_text,
             x0,
-            y0,
             max_width,
             scale,
             line_height,
-            show: true,
+            centered: true,
         };
-        gui.center();
+        gui.center(width*0.5);
         gui
     }
 
-    fn center(&mut self) {
-        self.x0 = self.x0 - 0.5*self.max_width;
+    /// Applies `line_fit` to every line and centers the panel again; `time` drives the marquee.
+    pub fn fit(&mut self, time: f64, fonts: &mut Fonts) {
+        let center = self.x0 + 0.5*self.max_width;
         for l in 0..self.lines.len() {
-            self.line_x[l] = self.x0 + 0.5*self.max_width - 0.5*self.line_width[l]
+            let (line, width, scroll) = fit_text(&self.lines[l], self.line_fit[l], self.fit_width, self.scale, time, fonts);
+            self.lines[l] = line;
+            self.line_width[l] = width;
+            self.line_scroll[l] = scroll;
         }
+        self.max_width = vec_max(&self.line_width);
+        self.center(center);
     }
 
-}
+    /// Horizontal range glyphs of line `l` are clipped to, if any.
+    pub fn clip(&self, l: usize) -> Option<(f32, f32)> {
+        clip_range(self.line_fit[l], self.line_x[l], self.line_width[l])
+    }
 
-fn char_to_uv(c: char) -> TextureUV {
-    let tex;
+    /// Thin strip between the last line and the bottom edge of the panel, in pixels.
+    pub fn progress_rect(&self) -> (f32, f32, f32, f32) {
+        let center = self.x0 + 0.5*self.max_width;
+        let x1 = center - 0.5*self.max_width;
+        let x2 = center + 0.5*self.max_width;
+        let y1 = self.line_y[self.lines.len() - 1] + 1.25*self.line_height;
+        let y2 = y1 + 0.3*self.line_height;
+        (x1, y1, x2, y2)
+    }
 
-    match c {
-        'A' => tex = TextureUV{u1: X1, u2: X1+WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'А' => tex = TextureUV{u1: X1, u2: X1+WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'Я' => tex = TextureUV{u1: X1, u2: X1+WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'B' => tex = TextureUV{u1: X1+WIDTH, u2: X1+2.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'Б' => tex = TextureUV{u1: X1+WIDTH, u2: X1+2.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'C' => tex = TextureUV{u1: X1+2.0*WIDTH, u2: X1+3.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'Ц' => tex = TextureUV{u1: X1+2.0*WIDTH, u2: X1+3.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'Ч' => tex = TextureUV{u1: X1+2.0*WIDTH, u2: X1+3.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'D' => tex = TextureUV{u1: X1+3.0*WIDTH, u2: X1+4.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'Д' => tex = TextureUV{u1: X1+3.0*WIDTH, u2: X1+4.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'E' => tex = TextureUV{u1: X1+4.0*WIDTH, u2: X1+5.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'Е' => tex = TextureUV{u1: X1+4.0*WIDTH, u2: X1+5.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'Ё' => tex = TextureUV{u1: X1+4.0*WIDTH, u2: X1+5.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'Э' => tex = TextureUV{u1: X1+4.0*WIDTH, u2: X1+5.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'F' => tex = TextureUV{u1: X1+5.0*WIDTH, u2: X1+6.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'Ф' => tex = TextureUV{u1: X1+5.0*WIDTH, u2: X1+6.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'G' => tex = TextureUV{u1: X1+6.0*WIDTH, u2: X1+7.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'Г' => tex = TextureUV{u1: X1+6.0*WIDTH, u2: X1+7.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'H' => tex = TextureUV{u1: X1+7.0*WIDTH, u2: X1+8.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'Х' => tex = TextureUV{u1: X1+7.0*WIDTH, u2: X1+8.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'I' => tex = TextureUV{u1: X1+8.0*WIDTH, u2: X1+9.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'И' => tex = TextureUV{u1: X1+8.0*WIDTH, u2: X1+9.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'J' => tex = TextureUV{u1: X1+9.0*WIDTH, u2: X1+10.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'Ж' => tex = TextureUV{u1: X1+9.0*WIDTH, u2: X1+10.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'K' => tex = TextureUV{u1: X1+10.0*WIDTH, u2: X1+11.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'К' => tex = TextureUV{u1: X1+10.0*WIDTH, u2: X1+11.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'L' => tex = TextureUV{u1: X1+11.0*WIDTH, u2: X1+12.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'Л' => tex = TextureUV{u1: X1+11.0*WIDTH, u2: X1+12.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'M' => tex = TextureUV{u1: X1+12.0*WIDTH, u2: X1+13.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        'М' => tex = TextureUV{u1: X1+12.0*WIDTH, u2: X1+13.0*WIDTH, v1: Y1, v2: Y1+HEIGHT_CAP},
-        '=' => tex = TextureUV{u1: 0.0, u2: 128.0, v1: 128.0, v2: 256.0},
-        'N' => tex = TextureUV{u1: X1+0.0*WIDTH, u2: X1+1.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'Н' => tex = TextureUV{u1: X1+0.0*WIDTH, u2: X1+1.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'O' => tex = TextureUV{u1: X1+1.0*WIDTH, u2: X1+2.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'О' => tex = TextureUV{u1: X1+1.0*WIDTH, u2: X1+2.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'P' => tex = TextureUV{u1: X1+2.0*WIDTH, u2: X1+3.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'П' => tex = TextureUV{u1: X1+2.0*WIDTH, u2: X1+3.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'Q' => tex = TextureUV{u1: X1+3.0*WIDTH, u2: X1+4.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'R' => tex = TextureUV{u1: X1+4.0*WIDTH, u2: X1+5.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'Р' => tex = TextureUV{u1: X1+4.0*WIDTH, u2: X1+5.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'р' => tex = TextureUV{u1: X1+4.0*WIDTH, u2: X1+5.0*WIDTH, v1: Y4, v2: Y4+HEIGHT_CAP},
-        'S' => tex = TextureUV{u1: X1+5.0*WIDTH, u2: X1+6.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'С' => tex = TextureUV{u1: X1+5.0*WIDTH, u2: X1+6.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'Ш' => tex = TextureUV{u1: X1+5.0*WIDTH, u2: X1+6.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'Щ' => tex = TextureUV{u1: X1+5.0*WIDTH, u2: X1+6.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'с' => tex = TextureUV{u1: X1+5.0*WIDTH, u2: X1+6.0*WIDTH, v1: Y4, v2: Y4+HEIGHT_CAP},
-        'T' => tex = TextureUV{u1: X1+6.0*WIDTH, u2: X1+7.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'Т' => tex = TextureUV{u1: X1+6.0*WIDTH, u2: X1+7.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'т' => tex = TextureUV{u1: X1+6.0*WIDTH, u2: X1+7.0*WIDTH, v1: Y4, v2: Y4+HEIGHT_CAP},
-        'U' => tex = TextureUV{u1: X1+7.0*WIDTH, u2: X1+8.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'У' => tex = TextureUV{u1: X1+7.0*WIDTH, u2: X1+8.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'у' => tex = TextureUV{u1: X1+7.0*WIDTH, u2: X1+8.0*WIDTH, v1: Y4, v2: Y4+HEIGHT_CAP},
-        'Ю' => tex = TextureUV{u1: X1+7.0*WIDTH, u2: X1+8.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'ю' => tex = TextureUV{u1: X1+7.0*WIDTH, u2: X1+8.0*WIDTH, v1: Y4, v2: Y4+HEIGHT_CAP},
-        'V' => tex = TextureUV{u1: X1+8.0*WIDTH, u2: X1+9.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'В' => tex = TextureUV{u1: X1+8.0*WIDTH, u2: X1+9.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'в' => tex = TextureUV{u1: X1+8.0*WIDTH, u2: X1+9.0*WIDTH, v1: Y4, v2: Y4+HEIGHT_CAP},
-        'W' => tex = TextureUV{u1: X1+9.0*WIDTH, u2: X1+10.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'X' => tex = TextureUV{u1: X1+10.0*WIDTH, u2: X1+11.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'Y' => tex = TextureUV{u1: X1+11.0*WIDTH, u2: X1+12.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'Й' => tex = TextureUV{u1: X1+11.0*WIDTH, u2: X1+12.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'Ы' => tex = TextureUV{u1: X1+11.0*WIDTH, u2: X1+12.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'й' => tex = TextureUV{u1: X1+11.0*WIDTH, u2: X1+12.0*WIDTH, v1: Y4, v2: Y4+HEIGHT_CAP},
-        'ы' => tex = TextureUV{u1: X1+11.0*WIDTH, u2: X1+12.0*WIDTH, v1: Y4, v2: Y4+HEIGHT_CAP},
-        'Z' => tex = TextureUV{u1: X1+12.0*WIDTH, u2: X1+13.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'З' => tex = TextureUV{u1: X1+12.0*WIDTH, u2: X1+13.0*WIDTH, v1: Y2, v2: Y2+HEIGHT_CAP},
-        'з' => tex = TextureUV{u1: X1+12.0*WIDTH, u2: X1+13.0*WIDTH, v1: Y4, v2: Y4+HEIGHT_CAP},
-        'a' => tex = TextureUV{u1: X1+0.0*WIDTH, u2: X1+1.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'а' => tex = TextureUV{u1: X1+0.0*WIDTH, u2: X1+1.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'я' => tex = TextureUV{u1: X1+0.0*WIDTH, u2: X1+1.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'b' => tex = TextureUV{u1: X1+1.0*WIDTH, u2: X1+2.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'б' => tex = TextureUV{u1: X1+1.0*WIDTH, u2: X1+2.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'c' => tex = TextureUV{u1: X1+2.0*WIDTH, u2: X1+3.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'ц' => tex = TextureUV{u1: X1+2.0*WIDTH, u2: X1+3.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'ч' => tex = TextureUV{u1: X1+2.0*WIDTH, u2: X1+3.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'd' => tex = TextureUV{u1: X1+3.0*WIDTH, u2: X1+4.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'д' => tex = TextureUV{u1: X1+3.0*WIDTH, u2: X1+4.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'e' => tex = TextureUV{u1: X1+4.0*WIDTH, u2: X1+5.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'е' => tex = TextureUV{u1: X1+4.0*WIDTH, u2: X1+5.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'ё' => tex = TextureUV{u1: X1+4.0*WIDTH, u2: X1+5.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'э' => tex = TextureUV{u1: X1+4.0*WIDTH, u2: X1+5.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'f' => tex = TextureUV{u1: X1+5.0*WIDTH, u2: X1+6.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'ф' => tex = TextureUV{u1: X1+5.0*WIDTH, u2: X1+6.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'g' => tex = TextureUV{u1: X1+6.0*WIDTH, u2: X1+7.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'г' => tex = TextureUV{u1: X1+6.0*WIDTH, u2: X1+7.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'h' => tex = TextureUV{u1: X1+7.0*WIDTH, u2: X1+8.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'х' => tex = TextureUV{u1: X1+7.0*WIDTH, u2: X1+8.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'i' => tex = TextureUV{u1: X1+8.0*WIDTH, u2: X1+9.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'и' => tex = TextureUV{u1: X1+8.0*WIDTH, u2: X1+9.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'j' => tex = TextureUV{u1: X1+9.0*WIDTH, u2: X1+10.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'ж' => tex = TextureUV{u1: X1+9.0*WIDTH, u2: X1+10.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'k' => tex = TextureUV{u1: X1+10.0*WIDTH, u2: X1+11.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'к' => tex = TextureUV{u1: X1+10.0*WIDTH, u2: X1+11.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'l' => tex = TextureUV{u1: X1+11.0*WIDTH, u2: X1+12.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'л' => tex = TextureUV{u1: X1+11.0*WIDTH, u2: X1+12.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'm' => tex = TextureUV{u1: X1+12.0*WIDTH, u2: X1+13.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'м' => tex = TextureUV{u1: X1+12.0*WIDTH, u2: X1+13.0*WIDTH, v1: Y3, v2: Y3+HEIGHT},
-        'n' => tex = TextureUV{u1: X1+0.0*WIDTH, u2: X1+1.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'н' => tex = TextureUV{u1: X1+0.0*WIDTH, u2: X1+1.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'o' => tex = TextureUV{u1: X1+1.0*WIDTH, u2: X1+2.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'о' => tex = TextureUV{u1: X1+1.0*WIDTH, u2: X1+2.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'p' => tex = TextureUV{u1: X1+2.0*WIDTH, u2: X1+3.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'п' => tex = TextureUV{u1: X1+2.0*WIDTH, u2: X1+3.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'q' => tex = TextureUV{u1: X1+3.0*WIDTH, u2: X1+4.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'r' => tex = TextureUV{u1: X1+4.0*WIDTH, u2: X1+5.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        's' => tex = TextureUV{u1: X1+5.0*WIDTH, u2: X1+6.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'ш' => tex = TextureUV{u1: X1+5.0*WIDTH, u2: X1+6.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'щ' => tex = TextureUV{u1: X1+5.0*WIDTH, u2: X1+6.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        't' => tex = TextureUV{u1: X1+6.0*WIDTH, u2: X1+7.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'u' => tex = TextureUV{u1: X1+7.0*WIDTH, u2: X1+8.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'v' => tex = TextureUV{u1: X1+8.0*WIDTH, u2: X1+9.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'w' => tex = TextureUV{u1: X1+9.0*WIDTH, u2: X1+10.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'x' => tex = TextureUV{u1: X1+10.0*WIDTH, u2: X1+11.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'y' => tex = TextureUV{u1: X1+11.0*WIDTH, u2: X1+12.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'z' => tex = TextureUV{u1: X1+12.0*WIDTH, u2: X1+13.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        '*' => tex = TextureUV{u1: X1+13.0*WIDTH, u2: X1+14.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        '“' => tex = TextureUV{u1: X1+14.0*WIDTH, u2: X1+15.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        '”' => tex = TextureUV{u1: X1+15.0*WIDTH, u2: X1+16.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        '!' => tex = TextureUV{u1: X1+16.0*WIDTH, u2: X1+17.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        '’' => tex = TextureUV{u1: X1+17.0*WIDTH, u2: X1+18.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'Ь' => tex = TextureUV{u1: X1+17.0*WIDTH, u2: X1+18.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'Ъ' => tex = TextureUV{u1: X1+17.0*WIDTH, u2: X1+18.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'ь' => tex = TextureUV{u1: X1+17.0*WIDTH, u2: X1+18.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        'ъ' => tex = TextureUV{u1: X1+17.0*WIDTH, u2: X1+18.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        '\'' => tex = TextureUV{u1: X1+17.0*WIDTH, u2: X1+18.0*WIDTH, v1: Y4, v2: Y4+HEIGHT},
-        '●' => tex = TextureUV{u1: X1+18.0*WIDTH+1.0, u2: X1+19.0*WIDTH-1.0, v1: 68.0, v2: 78.0},
-        '1' => tex = TextureUV{u1: X1+0.0*WIDTH, u2: X1+1.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        '2' => tex = TextureUV{u1: X1+1.0*WIDTH, u2: X1+2.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        '3' => tex = TextureUV{u1: X1+2.0*WIDTH, u2: X1+3.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        '4' => tex = TextureUV{u1: X1+3.0*WIDTH, u2: X1+4.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        '5' => tex = TextureUV{u1: X1+4.0*WIDTH, u2: X1+5.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        '6' => tex = TextureUV{u1: X1+5.0*WIDTH, u2: X1+6.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        '7' => tex = TextureUV{u1: X1+6.0*WIDTH, u2: X1+7.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        '8' => tex = TextureUV{u1: X1+7.0*WIDTH, u2: X1+8.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        '9' => tex = TextureUV{u1: X1+8.0*WIDTH, u2: X1+9.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        '0' => tex = TextureUV{u1: X1+9.0*WIDTH, u2: X1+10.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        ' ' => tex = TextureUV{u1: X1+10.0*WIDTH, u2: X1+11.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        '.' => tex = TextureUV{u1: X1+11.0*WIDTH, u2: X1+12.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        ':' => tex = TextureUV{u1: X1+12.0*WIDTH, u2: X1+13.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        ',' => tex = TextureUV{u1: X1+13.0*WIDTH, u2: X1+14.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        '?' => tex = TextureUV{u1: X1+14.0*WIDTH, u2: X1+15.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        '(' => tex = TextureUV{u1: X1+15.0*WIDTH, u2: X1+16.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        ')' => tex = TextureUV{u1: X1+16.0*WIDTH, u2: X1+17.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        ';' => tex = TextureUV{u1: X1+17.0*WIDTH, u2: X1+18.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        '■' => tex = TextureUV{u1: X1+18.0*WIDTH+1.0, u2: X1+19.0*WIDTH-1.0, v1: 88.0, v2: 98.0},
-        '[' => tex = TextureUV{u1: X1+19.0*WIDTH, u2: X1+20.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        ']' => tex = TextureUV{u1: X1+20.0*WIDTH, u2: X1+21.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
-        '+' => tex = TextureUV{u1: X1+13.0*WIDTH, u2: X1+14.0*WIDTH, v1: Y6, v2: Y6+HEIGHT},
-        '&' => tex = TextureUV{u1: X1+14.0*WIDTH, u2: X1+15.0*WIDTH, v1: Y6, v2: Y6+HEIGHT},
-        '_' => tex = TextureUV{u1: X1+15.0*WIDTH, u2: X1+16.0*WIDTH, v1: Y6, v2: Y6+HEIGHT},
-        '-' => tex = TextureUV{u1: X1+16.0*WIDTH, u2: X1+17.0*WIDTH, v1: Y6, v2: Y6+HEIGHT},
-        '—' => tex = TextureUV{u1: X1+16.0*WIDTH, u2: X1+17.0*WIDTH, v1: Y6, v2: Y6+HEIGHT},
-        '−' => tex = TextureUV{u1: X1+16.0*WIDTH, u2: X1+17.0*WIDTH, v1: Y6, v2: Y6+HEIGHT},
-        '–' => tex = TextureUV{u1: X1+16.0*WIDTH, u2: X1+17.0*WIDTH, v1: Y6, v2: Y6+HEIGHT},
-        '>' => tex = TextureUV{u1: X1+17.0*WIDTH, u2: X1+18.0*WIDTH, v1: Y6, v2: Y6+HEIGHT},
-        '<' => tex = TextureUV{u1: X1+18.0*WIDTH, u2: X1+19.0*WIDTH, v1: Y6, v2: Y6+HEIGHT},
-        _ => tex = TextureUV{u1: X1+10.0*WIDTH, u2: X1+11.0*WIDTH, v1: Y5, v2: Y5+HEIGHT},
+    /// Area under the panel reserved for the track overview, in pixels.
+    pub fn waveform_rect(&self) -> (f32, f32, f32, f32) {
+        let center = self.x0 + 0.5*self.max_width;
+        let x1 = center - 0.6*self.max_width;
+        let x2 = center + 0.6*self.max_width;
+        let y1 = self.line_y[self.lines.len() - 1] + 2.5*self.line_height;
+        let y2 = y1 + 2.0*self.line_height;
+        (x1, y1, x2, y2)
     }
 
-    tex
-}
+    /// Row of transport buttons below the track overview, in pixels.
+    pub fn transport_rect(&self) -> (f32, f32, f32, f32) {
+        let center = self.x0 + 0.5*self.max_width;
+        let x1 = center - 5.5*self.line_height;
+        let x2 = center + 5.5*self.line_height;
+        let y1 = self.line_y[self.lines.len() - 1] + 5.0*self.line_height;
+        let y2 = y1 + 1.5*self.line_height;
+        (x1, y1, x2, y2)
+    }
+
+    /// Row `n` of labelled sliders under the transport buttons, in pixels.
+    pub fn slider_rect(&self, n: usize) -> (f32, f32, f32, f32) {
+        let (x1, _, x2, y) = self.transport_rect();
+        let y1 = y + (0.5 + 1.5*n as f32)*self.line_height;
+        let y2 = y1 + 1.2*self.line_height;
+        (x1, y1, x2, y2)
+    }
 
-pub fn string_to_uv(text: &str) -> Vec<TextureUV> {
+    fn center(&mut self, center: f32) {
+        self.x0 = center - 0.5*self.max_width;
+        for l in 0..self.lines.len() {
+            self.line_x[l] = if self.centered {
+                self.x0 + 0.5*self.max_width - 0.5*self.line_width[l]
+            } else {
+                self.x0
+            }
+        }
+    }
 
-    let mut coords = Vec::new();
+}
 
+/// Fits `text` into `width` pixels.
+/// Returns the text to draw, the width it takes and, for the marquee, how far it is scrolled.
+fn fit_text(text: &str, fit: Fit, width: f32, scale: f32, time: f64, fonts: &mut Fonts) -> (String, f32, f32) {
+    // where each letter ends, so any head or tail of the text is measured without laying it out again
+    let ends: Vec<f32> = fonts.layout(text, scale).iter().map(|(pen, glyph)| pen + glyph.advance).collect();
+    let head = |n: usize| if n == 0 { 0.0 } else { ends[n - 1] };
+    let full = head(ends.len());
+    if full <= width || fit == Fit::Overflow {
+        return (text.to_string(), full, 0.0);
+    }
     let letters: Vec<char> = text.chars().collect();
+    let ellipsis = fonts.text_width(&ELLIPSIS.to_string(), scale);
+    let most = letters.len().saturating_sub(1);
+    match fit {
+        Fit::Ellipsis => {
+            let keep = most_fitting(most, |n| head(n) + ellipsis <= width);
+            let line: String = letters[..keep].iter().chain([ELLIPSIS].iter()).collect();
+            let line_width = fonts.text_width(&line, scale);
+            (line, line_width, 0.0)
+        }
+        Fit::Middle => {
+            let tail = |n: usize| full - head(letters.len() - n);
+            let keep = most_fitting(most, |n| head(n/2) + ellipsis + tail(n - n/2) <= width);
+            let (start, end) = (&letters[..keep/2], &letters[letters.len() - (keep - keep/2)..]);
+            let line: String = start.iter().chain([ELLIPSIS].iter()).chain(end.iter()).collect();
+            let line_width = fonts.text_width(&line, scale);
+            (line, line_width, 0.0)
+        }
+        _ => {
+            // pause at the start, scroll to the end, pause, scroll back
+            let overflow = full - width;
+            let travel = overflow/settings::MARQUEE_SPEED;
+            let period = 2.0*(travel + settings::MARQUEE_PAUSE);
+            let t = (time as f32) % period;
+            let scroll = if t < settings::MARQUEE_PAUSE {
+                0.0
+            } else if t < settings::MARQUEE_PAUSE + travel {
+                (t - settings::MARQUEE_PAUSE)*settings::MARQUEE_SPEED
+            } else if t < 2.0*settings::MARQUEE_PAUSE + travel {
+                overflow
+            } else {
+                overflow - (t - 2.0*settings::MARQUEE_PAUSE - travel)*settings::MARQUEE_SPEED
+            };
+            (text.to_string(), width, scroll)
+        }
+    }
+}
 
-    for letter in letters {
-        coords.push(char_to_uv(letter))
+/// The largest `n` up to `most` for which `fits(n)` holds, by bisection, as the kept
+/// text only grows wider with `n`. Zero when nothing fits.
+fn most_fitting(most: usize, fits: impl Fn(usize) -> bool) -> usize {
+    let (mut low, mut high) = (0, most);
+    while low < high {
+        let middle = (low + high).div_ceil(2);
+        if fits(middle) {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
     }
+    low
+}
 
-    for c in 0..coords.len() {
-        coords[c].normalize(TEXWIDTH, TEXHEIGHT);
+fn clip_range(fit: Fit, x: f32, width: f32) -> Option<(f32, f32)> {
+    if fit == Fit::Marquee {
+        Some((x, x + width))
+    } else {
+        None
     }
+}
 
-    coords
+/// Formats seconds as `mm:ss`, or `h:mm:ss` for long tracks.
+pub fn time_string(seconds: f64) -> String {
+    let total = seconds.max(0.0) as u64;
+    let (h, m, s) = (total / 3600, total / 60 % 60, total % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{:02}:{:02}", m, s)
+    }
 }
 
 fn vec_max(vect: &Vec<f32>) -> f32{
@@ -313,4 +291,29 @@ fn vec_max(vect: &Vec<f32>) -> f32{
         if m < vect[e] {m = vect[e]}
     }
     m
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cut_lines_keep_as_much_as_fits() {
+        let (mut fonts, _) = Fonts::load(None, &[]);
+        let text = "/music/Some Artist/Some Album/01 A rather long title.flac";
+        let full = fonts.text_width(text, 1.0);
+        for width in [0.0, 0.2 * full, 0.5 * full, 0.9 * full] {
+            for fit in [Fit::Ellipsis, Fit::Middle] {
+                let (line, line_width, _) = fit_text(text, fit, width, 1.0, 0.0, &mut fonts);
+                let kept = line.chars().count() - 1;
+                assert!(line.contains(ELLIPSIS));
+                assert!(line_width <= width || kept == 0, "{:?} {} wide for {}", line, line_width, width);
+                // the built-in font is monospace, so one letter more would not fit
+                let letter = fonts.text_width("a", 1.0);
+                assert!(line_width + letter > width, "{:?} could keep more in {}", line, width);
+            }
+        }
+        let (line, _, _) = fit_text(text, Fit::Middle, 0.5 * full, 1.0, 0.0, &mut fonts);
+        assert!(line.starts_with("/music/") && line.ends_with(".flac"), "{:?}", line);
+        assert_eq!(fit_text(text, Fit::Ellipsis, full, 1.0, 0.0, &mut fonts).0, text);
+    }
+}
@@ -0,0 +1,271 @@
+use serde::{Deserialize, Deserializer};
+use serde::de::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::settings;
+
+pub type Color = (f32, f32, f32, f32);
+
+pub const BUILTIN_FONT: &str = "monospace";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub name: String,
+    #[serde(deserialize_with = "hex_color")]
+    pub background: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub panel: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub text: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub active_text: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub overlay_text: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub accent: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub visualizer_low: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub visualizer_high: Color,
+    pub font: String,
+    pub fallback_fonts: Vec<String>,
+    pub font_scale: f32,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            name: "Default".to_string(),
+            background: (0.1568627, 0.1568627, 0.1568627, 1.0), // almost black
+            panel: (0.8282353, 0.8156863, 0.8266667, 1.0), // pale gray
+            text: (0.14117647, 0.07843137, 0.13333333, 1.0), // dark purple
+            active_text: (0.1843137, 0.2666667, 0.4627451, 1.0), // gray-blue
+            overlay_text: (0.85, 0.85, 0.85, 1.0), // almost white
+            accent: (0.9960784, 0.7607843, 0.5568627, 1.0), // pale yellow
+            visualizer_low: (0.0, 0.0, 0.85, 1.0),
+            visualizer_high: (0.85, 0.425, 0.0, 1.0),
+            font: BUILTIN_FONT.to_string(),
+            fallback_fonts: Vec::new(),
+            font_scale: 1.0,
+        }
+    }
+}
+
+impl Theme {
+    pub fn load(path: &Path) -> Result<Theme, String> {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", file_name, e))?;
+        let mut theme: Theme = toml::from_str(&text).map_err(|e| format!("{}: {}", file_name, e.message()))?;
+        if !(0.5..=3.0).contains(&theme.font_scale) {
+            return Err(format!("{}: font_scale must be between 0.5 and 3.0", file_name));
+        }
+        if theme.name.is_empty() || theme.name == Theme::default().name {
+            theme.name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        }
+        Ok(theme)
+    }
+
+    /// TrueType/OpenType font file, resolved relative to the themes directory.
+    /// `None` selects the built-in monospace font.
+    pub fn font_path(&self) -> Option<PathBuf> {
+        if self.font == BUILTIN_FONT {
+            None
+        } else {
+            Some(themes_dir().join(&self.font))
+        }
+    }
+
+    /// Fonts tried in order for characters the main font lacks.
+    pub fn fallback_paths(&self) -> Vec<PathBuf> {
+        self.fallback_fonts.iter().map(|f| themes_dir().join(f)).collect()
+    }
+}
+
+/// Linear blend from `a` at 0.0 to `b` at 1.0.
+pub fn mix(a: Color, b: Color, t: f32) -> Color {
+    (
+        a.0 + (b.0 - a.0) * t,
+        a.1 + (b.1 - a.1) * t,
+        a.2 + (b.2 - a.2) * t,
+        a.3 + (b.3 - a.3) * t,
+    )
+}
+
+/// Parses `#rrggbb` or `#rrggbbaa`.
+pub fn parse_hex(text: &str) -> Option<Color> {
+    let hex = text.strip_prefix('#')?;
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok().map(|c| c as f32 / 255.0);
+    let alpha = if hex.len() == 8 { channel(6)? } else { 1.0 };
+    Some((channel(0)?, channel(2)?, channel(4)?, alpha))
+}
+
+fn hex_color<'de, D>(deserializer: D) -> Result<Color, D::Error>
+    where D: Deserializer<'de>
+{
+    let text = String::deserialize(deserializer)?;
+    parse_hex(&text).ok_or_else(|| D::Error::custom(format!("invalid color \"{}\", expected #rrggbb or #rrggbbaa", text)))
+}
+
+pub fn themes_dir() -> PathBuf {
+    settings::config_dir().join("themes")
+}
+
+/// The built-in theme, from the `[colors]` of the configuration, followed by every
+/// `*.toml` file in the themes directory.
+/// The active file is watched and reloaded when it changes on disk.
+pub struct Themes {
+    pub list: Vec<Option<PathBuf>>,
+    pub current: usize,
+    pub theme: Theme,
+    builtin: Theme,
+    modified: Option<SystemTime>,
+    last_check: f64,
+}
+
+impl Themes {
+    /// Starts with the theme file called `name` from the themes directory. Without
+    /// such a file `default` is the built-in theme, any other name an error.
+    pub fn init(name: &str, builtin: Theme) -> (Themes, Option<String>) {
+        let mut themes = Themes {
+            list: scan(),
+            current: 0,
+            theme: builtin.clone(),
+            builtin,
+            modified: None,
+            last_check: 0.0,
+        };
+        let found = themes.list.iter().position(|p| {
+            p.as_ref().is_some_and(|p| p.file_stem().is_some_and(|s| s == name))
+        });
+        let error = match found {
+            Some(index) => themes.select(index).err(),
+            None if name == "default" => None,
+            None => Some(format!("no {}.toml in {}", name, themes_dir().display())),
+        };
+        (themes, error)
+    }
+
+    /// Replaces the built-in theme; `true` when it is the active one and changed.
+    pub fn set_builtin(&mut self, builtin: Theme) -> bool {
+        let changed = builtin != self.builtin;
+        self.builtin = builtin;
+        if changed && self.list[self.current].is_none() {
+            self.theme = self.builtin.clone();
+            return true;
+        }
+        false
+    }
+
+    /// Switches to the next theme, rescanning the directory first. Files that fail to
+    /// load are passed over, and their errors returned.
+    pub fn next(&mut self) -> Vec<String> {
+        let current = self.list[self.current].clone();
+        self.list = scan();
+        // the rescanned list may have moved the active file
+        self.current = self.list.iter().position(|p| *p == current).unwrap_or(0);
+        self.advance()
+    }
+
+    /// Tries the entries after the active one until one loads; the built-in theme
+    /// always does, so a broken file never blocks the ones after it.
+    fn advance(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for step in 1..=self.list.len() {
+            match self.select((self.current + step) % self.list.len()) {
+                Ok(()) => break,
+                Err(error) => errors.push(error),
+            }
+        }
+        errors
+    }
+
+    /// Makes the theme at `index` the active one; a file that fails to load leaves the
+    /// previous theme active and watched.
+    fn select(&mut self, index: usize) -> Result<(), String> {
+        let (theme, stamp) = match &self.list[index] {
+            Some(path) => {
+                let stamp = modified(path);
+                (Theme::load(path)?, stamp)
+            }
+            None => (self.builtin.clone(), None),
+        };
+        self.current = index;
+        self.theme = theme;
+        self.modified = stamp;
+        Ok(())
+    }
+
+    /// Checks the active file every `settings::THEME_POLL_TIME` seconds.
+    /// Returns `Some` when the file was reloaded, holding the error if it did not parse;
+    /// a broken file leaves the previous theme in place.
+    pub fn poll(&mut self, now: f64) -> Option<Result<(), String>> {
+        if now - self.last_check < settings::THEME_POLL_TIME {
+            return None;
+        }
+        self.last_check = now;
+        let path = self.list[self.current].clone()?;
+        let stamp = modified(&path);
+        if stamp.is_none() || stamp == self.modified {
+            return None;
+        }
+        self.modified = stamp;
+        Some(Theme::load(&path).map(|theme| self.theme = theme))
+    }
+}
+
+fn scan() -> Vec<Option<PathBuf>> {
+    let mut files: Vec<PathBuf> = match fs::read_dir(themes_dir()) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|x| x == "toml"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    files.sort();
+    let mut list = vec![None];
+    list.extend(files.into_iter().map(Some));
+    list
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_passes_over_a_broken_file() {
+        let dir = std::env::temp_dir().join(format!("srmp-themes-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = [("a.toml", "accent = \"#ff0000\""), ("b.toml", "accent = \"red\""), ("c.toml", "font_scale = 2.0")];
+        for (name, text) in files {
+            fs::write(dir.join(name), text).unwrap();
+        }
+        let mut themes = Themes {
+            list: vec![None, Some(dir.join("a.toml")), Some(dir.join("b.toml")), Some(dir.join("c.toml"))],
+            current: 1,
+            theme: Theme::default(),
+            builtin: Theme::default(),
+            modified: None,
+            last_check: 0.0,
+        };
+
+        let errors = themes.advance();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("b.toml: "));
+        assert_eq!((themes.current, themes.theme.name.as_str()), (3, "c"));
+
+        assert!(themes.advance().is_empty());
+        assert_eq!((themes.current, themes.theme.name.as_str()), (0, "Default"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,262 @@
+/// What a transport button does when clicked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transport {
+    Previous,
+    PlayPause,
+    Stop,
+    Next,
+}
+
+/// Clickable box in texture pixels, drawn as a small panel with a centered label.
+pub struct Button {
+    pub action: Transport,
+    pub label: String,
+    pub rect: (f32, f32, f32, f32),
+    pub hover: bool,
+    pub pressed: bool,
+}
+
+impl Button {
+    pub fn new(action: Transport, label: &str) -> Button {
+        Button {
+            action,
+            label: label.to_string(),
+            rect: (0.0, 0.0, 0.0, 0.0),
+            hover: false,
+            pressed: false,
+        }
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        let (x1, y1, x2, y2) = self.rect;
+        x >= x1 && x <= x2 && y >= y1 && y <= y2
+    }
+
+    /// Highlight for the GUI shader: half way when hovered, full when held down.
+    pub fn act(&self) -> f32 {
+        if self.pressed {
+            1.0
+        } else if self.hover {
+            0.5
+        } else {
+            0.0
+        }
+    }
+}
+
+pub fn transport() -> Vec<Button> {
+    vec![
+        Button::new(Transport::Previous, "|◀"),
+        Button::new(Transport::PlayPause, "▮▮"),
+        Button::new(Transport::Stop, "■"),
+        Button::new(Transport::Next, "▶|"),
+    ]
+}
+
+/// Spreads `buttons` evenly over `rect`, leaving a gap of `gap` pixels between them.
+pub fn layout_row(buttons: &mut [Button], rect: (f32, f32, f32, f32), gap: f32) {
+    let (x1, y1, x2, y2) = rect;
+    let n = buttons.len() as f32;
+    let width = (x2 - x1 - gap * (n - 1.0)) / n;
+    for (i, button) in buttons.iter_mut().enumerate() {
+        let x = x1 + i as f32 * (width + gap);
+        button.rect = (x, y1, x + width, y2);
+    }
+}
+
+/// Updates the hover state from the pointer position.
+pub fn hover(buttons: &mut [Button], x: f32, y: f32) {
+    for button in buttons.iter_mut() {
+        button.hover = button.contains(x, y);
+    }
+}
+
+/// Which value a slider edits; the stage decides what a change means.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SliderId {
+    Seek,
+    Volume,
+    Gain,
+}
+
+/// Horizontal slider between `min` and `max`; the mouse wheel and the arrow keys move it by `step`.
+/// A labelled slider sits in its own small panel with the label on the left.
+pub struct Slider {
+    pub id: SliderId,
+    pub label: String,
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+    pub rect: (f32, f32, f32, f32),
+    pub hover: bool,
+    pub dragging: bool,
+    pub focused: bool,
+}
+
+impl Slider {
+    pub fn new(id: SliderId, value: f32, min: f32, max: f32, step: f32) -> Slider {
+        Slider {
+            id,
+            label: String::new(),
+            value,
+            min,
+            max,
+            step,
+            rect: (0.0, 0.0, 0.0, 0.0),
+            hover: false,
+            dragging: false,
+            focused: false,
+        }
+    }
+
+    /// Position of the value along the track, 0.0 to 1.0.
+    pub fn fraction(&self) -> f32 {
+        if self.max > self.min {
+            ((self.value - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// The bar itself: the whole rectangle without a label, otherwise a thin strip right of it.
+    pub fn track(&self) -> (f32, f32, f32, f32) {
+        let (x1, y1, x2, y2) = self.rect;
+        if self.label.is_empty() {
+            return self.rect;
+        }
+        let mid = 0.5 * (y1 + y2);
+        let half = 0.125 * (y2 - y1);
+        (x1 + 0.4 * (x2 - x1), mid - half, x2 - 0.05 * (x2 - x1), mid + half)
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        let (x1, y1, x2, y2) = self.rect;
+        x >= x1 && x <= x2 && y >= y1 && y <= y2
+    }
+
+    fn set_from_x(&mut self, x: f32) {
+        let (x1, _, x2, _) = self.track();
+        let t = ((x - x1) / (x2 - x1).max(1.0)).clamp(0.0, 1.0);
+        self.value = self.min + t * (self.max - self.min);
+    }
+
+    fn step_by(&mut self, steps: f32) {
+        self.value = (self.value + steps * self.step).clamp(self.min, self.max);
+    }
+}
+
+/// Something the user did to a widget.
+/// Sliders report `Changed` while dragged and `Released` once the value is settled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    Clicked(Transport),
+    Changed(SliderId, f32),
+    Released(SliderId, f32),
+}
+
+/// The retained widgets of the player: pointer, drag and keyboard focus state
+/// survive between frames while the GUI text is rebuilt every frame.
+pub struct Widgets {
+    pub buttons: Vec<Button>,
+    pub sliders: Vec<Slider>,
+    pointer: (f32, f32),
+    drag: Option<usize>,
+}
+
+impl Widgets {
+    pub fn init(volume: f32) -> Widgets {
+        let mut volume = Slider::new(SliderId::Volume, volume, 0.0, 1.0, 0.05);
+        volume.label = "Volume".to_string();
+        let mut gain = Slider::new(SliderId::Gain, 1.0, 0.25, 4.0, 0.25);
+        gain.label = "Gain".to_string();
+        Widgets {
+            buttons: transport(),
+            sliders: vec![Slider::new(SliderId::Seek, 0.0, 0.0, 0.0, 5.0), volume, gain],
+            pointer: (-1.0, -1.0),
+            drag: None,
+        }
+    }
+
+    pub fn slider(&self, id: SliderId) -> &Slider {
+        self.sliders.iter().find(|s| s.id == id).expect("unknown slider")
+    }
+
+    pub fn slider_mut(&mut self, id: SliderId) -> &mut Slider {
+        self.sliders.iter_mut().find(|s| s.id == id).expect("unknown slider")
+    }
+
+    /// Presses a button or grabs a slider, which also takes the keyboard focus.
+    pub fn mouse_down(&mut self, x: f32, y: f32) -> Option<Event> {
+        for button in self.buttons.iter_mut() {
+            button.pressed = button.contains(x, y);
+        }
+        let hit = self.sliders.iter().position(|s| s.contains(x, y));
+        for (i, slider) in self.sliders.iter_mut().enumerate() {
+            slider.focused = Some(i) == hit;
+        }
+        let i = hit?;
+        self.drag = Some(i);
+        let slider = &mut self.sliders[i];
+        slider.dragging = true;
+        slider.set_from_x(x);
+        Some(Event::Changed(slider.id, slider.value))
+    }
+
+    pub fn mouse_up(&mut self, x: f32, y: f32) -> Option<Event> {
+        let clicked = self.buttons.iter().find(|b| b.pressed && b.contains(x, y)).map(|b| b.action);
+        for button in self.buttons.iter_mut() {
+            button.pressed = false;
+        }
+        if let Some(i) = self.drag.take() {
+            let slider = &mut self.sliders[i];
+            slider.dragging = false;
+            slider.set_from_x(x);
+            return Some(Event::Released(slider.id, slider.value));
+        }
+        clicked.map(Event::Clicked)
+    }
+
+    pub fn mouse_move(&mut self, x: f32, y: f32) -> Option<Event> {
+        self.pointer = (x, y);
+        hover(&mut self.buttons, x, y);
+        for slider in self.sliders.iter_mut() {
+            slider.hover = slider.contains(x, y);
+        }
+        let slider = &mut self.sliders[self.drag?];
+        slider.set_from_x(x);
+        Some(Event::Changed(slider.id, slider.value))
+    }
+
+    /// Moves the slider under the pointer by one step per wheel notch.
+    pub fn wheel(&mut self, dy: f32) -> Option<Event> {
+        let (x, y) = self.pointer;
+        let slider = self.sliders.iter_mut().find(|s| s.contains(x, y))?;
+        slider.step_by(dy.signum());
+        Some(Event::Released(slider.id, slider.value))
+    }
+
+    /// Passes the keyboard focus to the next slider, or to none after the last one.
+    pub fn focus_next(&mut self) {
+        let next = match self.sliders.iter().position(|s| s.focused) {
+            Some(i) => i + 1,
+            None => 0,
+        };
+        for (i, slider) in self.sliders.iter_mut().enumerate() {
+            slider.focused = i == next;
+        }
+    }
+
+    /// Moves a slider by `steps` steps, as if it had the focus.
+    pub fn step(&mut self, id: SliderId, steps: f32) -> Event {
+        let slider = self.slider_mut(id);
+        slider.step_by(steps);
+        Event::Released(slider.id, slider.value)
+    }
+
+    pub fn step_focused(&mut self, steps: f32) -> Option<Event> {
+        let slider = self.sliders.iter_mut().find(|s| s.focused)?;
+        slider.step_by(steps);
+        Some(Event::Released(slider.id, slider.value))
+    }
+}